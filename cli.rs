@@ -0,0 +1,71 @@
+// ============================================================
+//  cli.rs — Headless `--eval` mode
+//  casio-calc --eval "2+3*4" [--angle deg|rad|gra] [--format norm|sci|sci=N|eng|fix=N]
+// ============================================================
+
+use crate::engine::{AngleMode, CalcEngine, DisplayFormat, SCI_DIGITS};
+
+/// Options for one headless evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalArgs {
+    pub expr:   String,
+    pub angle:  AngleMode,
+    pub format: DisplayFormat,
+}
+
+/// `Ok(None)` when there is no `--eval` (start the GUI); `Err` on a bad flag
+pub fn parse_args(args: &[String]) -> Result<Option<EvalArgs>, String> {
+    let mut expr = None;
+    let mut angle = AngleMode::Degrees;
+    let mut format = DisplayFormat::Normal;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--eval"   => expr = Some(value()?.clone()),
+            "--angle"  => angle = parse_angle(value()?)?,
+            "--format" => format = parse_format(value()?)?,
+            other      => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+
+    Ok(expr.map(|expr| EvalArgs { expr, angle, format }))
+}
+
+fn parse_angle(s: &str) -> Result<AngleMode, String> {
+    match s {
+        "deg" => Ok(AngleMode::Degrees),
+        "rad" => Ok(AngleMode::Radians),
+        "gra" => Ok(AngleMode::Gradians),
+        _     => Err(format!("Unknown angle mode: {} (deg|rad|gra)", s)),
+    }
+}
+
+fn parse_format(s: &str) -> Result<DisplayFormat, String> {
+    if let Some(n) = s.strip_prefix("sci=") {
+        return n.parse::<u8>().ok()
+            .filter(|n| SCI_DIGITS.contains(n))
+            .map(DisplayFormat::Scientific)
+            .ok_or(format!("Unknown format: {} (sci={}..{})", s, SCI_DIGITS.start(), SCI_DIGITS.end()));
+    }
+    match s {
+        "norm" => Ok(DisplayFormat::Normal),
+        "sci"  => Ok(DisplayFormat::Scientific(*SCI_DIGITS.end())),
+        "eng"  => Ok(DisplayFormat::Engineering),
+        _ => s.strip_prefix("fix=")
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|&n| n <= 9)
+            .map(DisplayFormat::Fix)
+            .ok_or(format!("Unknown format: {} (norm|sci|sci=N|eng|fix=0..9)", s)),
+    }
+}
+
+/// Evaluate and format the way the display would
+pub fn run(args: &EvalArgs) -> Result<String, String> {
+    let mut engine = CalcEngine::new();
+    engine.angle = args.angle;
+    engine.format = args.format;
+    let val = engine.evaluate(&args.expr)?;
+    Ok(engine.format_result(val))
+}
@@ -3,7 +3,10 @@
 //  Full scientific calculator: tokenizer → parser → evaluator
 // ============================================================
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
 use std::f64::consts::{PI, E};
 
 // ─────────────────────────── TOKENS ────────────────────────
@@ -11,10 +14,18 @@ use std::f64::consts::{PI, E};
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Number(f64),
-    Plus, Minus, Mul, Div, Pow,
+    /// Sexagesimal literal (1°30'15") already folded to decimal degrees
+    Dms(f64),
+    Plus, Minus, Mul, Div, Pow, Root,
+    And, Or, Xor, Not, Shl, Shr,
+    Eq, Lt, Gt, Le, Ge,
     LParen, RParen,
     Func(String),
-    Const(String),
+    /// π, e and Ans: a value by name, so `2π` and `2Ans` read as products
+    /// where two bare numbers `1 2` do not
+    Const(f64),
+    Var(char),
+    Assign,
     Comma,
     Factorial,
     Percent,
@@ -44,6 +55,22 @@ impl AngleMode {
             AngleMode::Gradians => v * 200.0 / PI,
         }
     }
+    /// 90° / π⁄2 rad / 100ᵍ in this unit
+    pub fn quarter_turn(self) -> f64 {
+        match self {
+            AngleMode::Degrees  => 90.0,
+            AngleMode::Radians  => PI / 2.0,
+            AngleMode::Gradians => 100.0,
+        }
+    }
+    /// Suffix written after an angle value on the display
+    pub fn unit(self) -> &'static str {
+        match self {
+            AngleMode::Degrees  => "°",
+            AngleMode::Radians  => "",
+            AngleMode::Gradians => "ᵍ",
+        }
+    }
     pub fn label(self) -> &'static str {
         match self {
             AngleMode::Degrees  => "D",
@@ -58,11 +85,45 @@ impl AngleMode {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DisplayFormat {
     Normal,
-    Scientific,
+    /// Significant digits, within `SCI_DIGITS`
+    Scientific(u8),
     Engineering,
     Fix(u8),
 }
 
+// ─────────────────────────── COORDINATE FORM ───────────────
+
+/// How Pol/Rec coordinate pairs are shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordForm {
+    Rectangular,   // (x,y)
+    Polar,         // r∠θ
+}
+
+/// Memory variable names, in display order
+pub const VARIABLES: [char; 9] = ['A','B','C','D','E','F','X','Y','M'];
+
+/// Default word size for bitwise operators (two's complement)
+pub const WORD_BITS: u32 = 32;
+
+/// Error for input with nothing to calculate (blank, or only an operator)
+pub const EMPTY_INPUT: &str = "Syntax ERROR (empty)";
+
+/// Entries kept by the `try_evaluate` cache
+const CACHE_CAPACITY: usize = 32;
+
+/// Default number of past calculations kept in `history`
+pub const HISTORY_CAPACITY: usize = 50;
+
+/// Below this magnitude Normal format falls back to scientific
+pub const SCI_LOWER: f64 = 1e-9;
+/// Significant digits the Sci setup offers (Sci 2 … Sci 9)
+pub const SCI_DIGITS: RangeInclusive<u8> = 2..=9;
+/// Significant digits on a standard 10-digit display
+pub const DISPLAY_DIGITS: usize = 10;
+/// Default upper magnitude for Normal format (10-digit display)
+pub const SCI_UPPER: f64 = 1e10;
+
 // ─────────────────────────── ENGINE ────────────────────────
 
 pub struct CalcEngine {
@@ -71,13 +132,55 @@ pub struct CalcEngine {
     pub ans:    f64,
     pub memory: HashMap<char, f64>,
     pub m_plus: f64,
-    pub history: Vec<(String, f64)>,
+    pub history: VecDeque<(String, f64)>,
+    pub history_capacity: usize,
+    pub digits:  usize,
+    /// Word size of the bitwise operators, 1..=64 (see `set_word_bits`)
+    word_bits: u32,
+    /// Base-N (integer) mode: the only place `and`, `or`, `xor`, `not`,
+    /// `<<` and `>>` work; elsewhere they are a Math ERROR
+    pub base_n: bool,
+    pub sci_upper: f64,
+    pub coord_form: CoordForm,
+    /// (x, y) of the last Pol/Rec evaluation, if the last result was one
+    pub coords: Option<(f64, f64)>,
+    /// Whether the last result came from °'" input and should show as DMS
+    pub dms: bool,
+    /// a b/c display: rational results show as fractions
+    pub fractions: bool,
+    /// Fractions above 1 as mixed numbers (3⌟1⌟2) rather than improper (7⌟2)
+    pub mixed: bool,
+    /// Read SI suffixes on literals (4.7k, 1M, 2.2µ); off keeps `2M` as 2×M
+    pub si_suffixes: bool,
+    /// European locale: `3,14` decimals, `1.234` grouping, `;` between arguments
+    pub comma_decimal: bool,
+    /// Flag degree-mode trig of radian-looking arguments (`sin(3.14)`)
+    pub radian_hint: bool,
+    /// The last evaluation tripped `radian_hint`; the UI shows DEG?
+    pub deg_warning: bool,
+    /// `Ans` reads as the rounded value on the display, not the full f64
+    pub rounded_ans: bool,
+    /// Recent `try_evaluate` results, most recent first
+    cache: RefCell<VecDeque<(CacheKey, Result<f64, String>)>>,
+}
+
+/// Everything a `try_evaluate` result depends on. Ans and the variables are
+/// part of the key, so changing them (or the angle mode) simply misses.
+#[derive(Debug, Clone, PartialEq)]
+struct CacheKey {
+    expr:   String,
+    angle:  AngleMode,
+    ans:    u64,
+    memory: [u64; VARIABLES.len()],
+    bits:   Option<u32>,
+    si:     bool,
+    comma:  bool,
 }
 
 impl Default for CalcEngine {
     fn default() -> Self {
         let mut memory = HashMap::new();
-        for c in ['A','B','C','D','E','F','X','Y','M'] {
+        for c in VARIABLES {
             memory.insert(c, 0.0);
         }
         Self {
@@ -86,7 +189,23 @@ impl Default for CalcEngine {
             ans:     0.0,
             memory,
             m_plus:  0.0,
-            history: Vec::new(),
+            history: VecDeque::with_capacity(HISTORY_CAPACITY),
+            history_capacity: HISTORY_CAPACITY,
+            digits:  DISPLAY_DIGITS,
+            word_bits: WORD_BITS,
+            base_n:  false,
+            sci_upper: SCI_UPPER,
+            coord_form: CoordForm::Rectangular,
+            coords:  None,
+            dms:     false,
+            fractions: false,
+            mixed:   true,
+            si_suffixes: false,
+            comma_decimal: false,
+            radian_hint: false,
+            deg_warning: false,
+            rounded_ans: false,
+            cache:   RefCell::new(VecDeque::with_capacity(CACHE_CAPACITY)),
         }
     }
 }
@@ -102,6 +221,43 @@ impl CalcEngine {
         };
     }
 
+    /// CLR 1: angle unit and display format back to defaults
+    pub fn clear_setup(&mut self) {
+        self.angle  = AngleMode::Degrees;
+        self.format = DisplayFormat::Normal;
+        self.coord_form = CoordForm::Rectangular;
+        self.fractions = false;
+        self.mixed = true;
+    }
+
+    /// CLR 2: variables, M and Ans to zero
+    pub fn clear_memory(&mut self) {
+        for v in self.memory.values_mut() { *v = 0.0; }
+        self.m_plus = 0.0;
+        self.ans    = 0.0;
+    }
+
+    /// CLR 3: setup + memory + history
+    pub fn clear_all(&mut self) {
+        self.clear_setup();
+        self.clear_memory();
+        self.clear_history();
+    }
+
+    /// Change how many calculations are kept, dropping the oldest if over
+    pub fn set_history_capacity(&mut self, n: usize) {
+        self.history_capacity = n;
+        while self.history.len() > n { self.history.pop_front(); }
+    }
+
+    pub fn clear_history(&mut self) { self.history.clear(); }
+
+    fn record_history(&mut self, expr: &str, result: f64) {
+        if self.history_capacity == 0 { return; }
+        if self.history.len() == self.history_capacity { self.history.pop_front(); }
+        self.history.push_back((expr.to_string(), result));
+    }
+
     pub fn store(&mut self, var: char, val: f64) {
         self.memory.insert(var, val);
     }
@@ -110,69 +266,464 @@ impl CalcEngine {
         *self.memory.get(&var).unwrap_or(&0.0)
     }
 
+    /// Every variable and its value in `VARIABLES` order, then the M+ accumulator
+    pub fn variables(&self) -> Vec<(String, f64)> {
+        let mut vars: Vec<(String, f64)> = VARIABLES.iter()
+            .map(|&c| (c.to_string(), self.recall(c)))
+            .collect();
+        vars.push(("M+".to_string(), self.m_plus));
+        vars
+    }
+
     pub fn m_plus_op(&mut self, val: f64) { self.m_plus += val; }
     pub fn m_minus_op(&mut self, val: f64) { self.m_plus -= val; }
     pub fn recall_m(&self) -> f64 { self.m_plus }
     pub fn clear_m(&mut self) { self.m_plus = 0.0; }
 
-    /// Format a number for the CASIO display (10 digits max)
+    /// True while the M+ accumulator holds a value (lights the M indicator)
+    pub fn memory_in_use(&self) -> bool { self.m_plus != 0.0 }
+
+    /// Digit budget of the display; Normal format switches to scientific
+    /// once an integer part would no longer fit
+    pub fn set_display_digits(&mut self, digits: usize) {
+        self.digits = digits;
+        self.sci_upper = 10f64.powi(digits as i32);
+    }
+
+    /// Word size of the bitwise operators, clamped to 1..=64 bits
+    pub fn set_word_bits(&mut self, bits: u32) {
+        self.word_bits = bits.clamp(1, 64);
+    }
+
+    /// Format a number for the CASIO display (`digits` significant digits max)
     pub fn format_result(&self, val: f64) -> String {
         if val.is_nan()      { return "Math ERROR".to_string(); }
         if val.is_infinite() { return if val > 0.0 { "∞".to_string() } else { "-∞".to_string() }; }
 
-        match self.format {
-            DisplayFormat::Scientific  => format_scientific(val, 9),
+        let s = match self.format {
+            DisplayFormat::Scientific(n) => format_sci_digits(val, n as usize),
             DisplayFormat::Engineering => format_engineering(val),
             DisplayFormat::Fix(n)      => format!("{:.prec$}", val, prec = n as usize),
-            DisplayFormat::Normal      => format_normal(val),
+            DisplayFormat::Normal      => format_normal(val, self.digits, self.sci_upper),
+        };
+        if self.comma_decimal { to_comma_locale(&s) } else { s }
+    }
+
+    /// `val` rounded the way the current format displays it
+    pub fn displayed_value(&self, val: f64) -> f64 {
+        if !val.is_finite() || val == 0.0 { return val; }
+        // Round through the decimal text so 0.33 is the double nearest 0.33
+        let sig = |digits: usize| format!("{:.*e}", digits.saturating_sub(1), val).parse().unwrap_or(val);
+        match self.format {
+            DisplayFormat::Fix(n)        => format!("{:.*}", n as usize, val).parse().unwrap_or(val),
+            DisplayFormat::Scientific(n) => sig(n as usize),
+            DisplayFormat::Normal        => sig(self.digits),
+            DisplayFormat::Engineering   => {
+                // Three decimals on a mantissa of one to three integer digits
+                let exp = val.abs().log10().floor() as i32;
+                let eng_exp = (exp as f64 / 3.0).floor() as i32 * 3;
+                sig((exp - eng_exp + 4) as usize)
+            }
         }
     }
 
+    /// What `Ans` reads as in an expression
+    fn ans_value(&self) -> f64 {
+        if self.rounded_ans { self.displayed_value(self.ans) } else { self.ans }
+    }
+
+    /// Every digit the f64 holds (the shortest form that reads back exactly),
+    /// for comparing against the rounded display: 1/3 → 0.3333333333333333
+    pub fn format_full(&self, val: f64) -> String {
+        if !val.is_finite() { return self.format_result(val); }
+        let abs = val.abs();
+        let s = if val == 0.0 || (1e-5..1e16).contains(&abs) {
+            format!("{}", val)
+        } else {
+            let s = format!("{:e}", val);
+            let (m, e) = s.split_once('e').unwrap_or((&s, "0"));
+            format!("{}×10^{}", m, e)
+        };
+        if self.comma_decimal { to_comma_locale(&s) } else { s }
+    }
+
+    /// A coordinate pair in the current `coord_form`, θ in the current angle unit
+    pub fn format_coords(&self, x: f64, y: f64) -> String {
+        match self.coord_form {
+            CoordForm::Rectangular => {
+                let sep = if self.comma_decimal { ';' } else { ',' };
+                format!("({}{}{})", self.format_result(x), sep, self.format_result(y))
+            }
+            CoordForm::Polar => {
+                let r = x.hypot(y);
+                let theta = self.angle.from_rad(y.atan2(x));
+                format!("{}∠{}{}", self.format_result(r), self.format_result(theta), self.angle.unit())
+            }
+        }
+    }
+
+    /// Decimal degrees (or hours) as d°m's", carrying rounded seconds upward
+    pub fn format_dms(&self, val: f64) -> String {
+        let sign = if val < 0.0 { "-" } else { "" };
+        let total = (val.abs() * 3600.0 * 100.0).round() / 100.0;
+        let d = (total / 3600.0).floor();
+        let m = ((total - d * 3600.0) / 60.0).floor();
+        let s = total - d * 3600.0 - m * 60.0;
+        let s = format_normal(s, self.digits, self.sci_upper);
+        let s = if self.comma_decimal { to_comma_locale(&s) } else { s };
+        format!("{}{}°{}'{}\"", sign, d, m, s)
+    }
+
+    /// `val` in a b/c form, or `None` if it is not a fraction with a small denominator
+    pub fn format_fraction(&self, val: f64) -> Option<String> {
+        let (n, d) = to_fraction(val, FRACTION_MAX_DEN, FRACTION_TOLERANCE)?;
+        Some(format_fraction_parts(n, d, self.mixed))
+    }
+
+    /// F↔D: the closest simple fraction to a decimal, so a rounded `0.333333333`
+    /// still converts to 1⌟3; irrationals find none and stay decimal
+    pub fn approximate_fraction(&self, val: f64) -> Option<String> {
+        let (n, d) = to_fraction(val, FRACTION_MAX_DEN, FRACTION_APPROX_TOLERANCE)?;
+        Some(format_fraction_parts(n, d, self.mixed))
+    }
+
+    pub fn toggle_coord_form(&mut self) {
+        self.coord_form = match self.coord_form {
+            CoordForm::Rectangular => CoordForm::Polar,
+            CoordForm::Polar       => CoordForm::Rectangular,
+        };
+    }
+
+    /// True when Normal format had to switch `val` to scientific form
+    pub fn is_auto_sci(&self, val: f64) -> bool {
+        self.format == DisplayFormat::Normal && val.is_finite() && needs_auto_sci(val, self.sci_upper)
+    }
+
     /// Evaluate a string expression
     pub fn evaluate(&mut self, expr: &str) -> Result<f64, String> {
-        let tokens = tokenize(expr, self.ans, &self.memory)?;
-        let mut parser = Parser::new(tokens, self.angle);
-        let result = parser.parse_expr()?;
+        self.evaluate_with(expr, &mut Scratch::default())
+    }
 
-        if result.is_nan()      { return Err("Math ERROR".to_string()); }
-        if result.is_infinite() { return Err("Math ERROR (overflow)".to_string()); }
+    fn evaluate_with(&mut self, expr: &str, scratch: &mut Scratch) -> Result<f64, String> {
+        let Outcome { value: result, assigned, coords, dms, radian_like } = self.compute(expr, scratch)?;
+
+        // Assignments only land once the whole expression succeeded
+        for (var, val) in assigned {
+            self.store(var, val);
+        }
+        self.coords = coords;
+        self.dms = dms;
+        self.deg_warning = self.radian_hint && radian_like;
 
         self.ans = result;
-        self.history.push((expr.to_string(), result));
-        if self.history.len() > 50 { self.history.remove(0); }
+        self.record_history(expr, result);
+        // Everything cached was keyed on the old Ans; drop it rather than let it age out
+        self.cache.get_mut().clear();
 
         Ok(result)
     }
+
+    /// Evaluate a batch in order, so each `Ans` is the previous result
+    /// (regression suites, scripting, parser throughput runs). The
+    /// tokenizer's buffers are kept from one expression to the next.
+    pub fn evaluate_many(&mut self, exprs: &[&str]) -> Vec<Result<f64, String>> {
+        let mut scratch = Scratch::default();
+        exprs.iter().map(|expr| self.evaluate_with(expr, &mut scratch)).collect()
+    }
+
+    /// Evaluate without touching Ans, history or memory (previews, sampling)
+    /// Repeats (live preview redraws, table rows) come from a small LRU cache.
+    pub fn try_evaluate(&self, expr: &str) -> Result<f64, String> {
+        let key = self.cache_key(expr);
+        let mut cache = self.cache.borrow_mut();
+        if let Some(at) = cache.iter().position(|(k, _)| *k == key) {
+            let hit = cache.remove(at).expect("position came from iter");
+            let result = hit.1.clone();
+            cache.push_front(hit);
+            return result;
+        }
+
+        let result = self.compute(expr, &mut Scratch::default()).map(|outcome| outcome.value);
+        if cache.len() == CACHE_CAPACITY { cache.pop_back(); }
+        cache.push_front((key, result.clone()));
+        result
+    }
+
+    /// Memory variables a formula reads, in order of first use (what CALC
+    /// asks for). Assignment targets (`A=`, `→A`) are written, not read.
+    pub fn formula_variables(&self, expr: &str) -> Result<Vec<char>, String> {
+        let tokens = tokenize(&self.delocalize(expr), self.ans_value(), self.si_suffixes)?;
+        let mut vars = Vec::new();
+        for (i, t) in tokens.iter().enumerate() {
+            let Token::Var(v) = t else { continue };
+            let target = (i > 0 && tokens[i - 1] == Token::Assign) || tokens.get(i + 1) == Some(&Token::Assign);
+            if !target && !vars.contains(v) {
+                vars.push(*v);
+            }
+        }
+        Ok(vars)
+    }
+
+    /// The token stream `expr` reads as, for the developer overlay
+    pub fn debug_tokens(&self, expr: &str) -> Result<Vec<Token>, String> {
+        tokenize(&self.delocalize(expr), self.ans_value(), self.si_suffixes)
+    }
+
+    fn cache_key(&self, expr: &str) -> CacheKey {
+        CacheKey {
+            expr:   expr.trim().to_string(),
+            angle:  self.angle,
+            ans:    self.ans_value().to_bits(),
+            memory: VARIABLES.map(|v| self.recall(v).to_bits()),
+            bits:   self.base_n.then_some(self.word_bits),
+            si:     self.si_suffixes,
+            comma:  self.comma_decimal,
+        }
+    }
+
+    /// Parse without evaluating. The tree can be `eval`ed with `context()`
+    /// as often as needed, e.g. once per X with `EvalContext::x` set.
+    pub fn parse(&self, expr: &str) -> Result<Expr, String> {
+        let mut scratch = Scratch::default();
+        self.lex(expr, &mut scratch)?;
+        parse_tokens(&scratch.tokens)
+    }
+
+    /// Tokenize and parse `expr` without evaluating or touching any state,
+    /// for live error highlighting. Stricter than `=`: a dangling operator
+    /// that `=` would drop is still reported, as `Incomplete`.
+    pub fn validate(&self, expr: &str) -> Result<(), Diagnostic> {
+        let source = expr;
+        let expr = &*self.delocalize(source);
+        // Positions below are into `expr`; report them in the text as typed
+        let at = |pos: usize| if self.comma_decimal { comma_source_index(source, pos) } else { pos };
+        if let Some(pos) = unmatched_close(expr) {
+            return Err(Diagnostic {
+                kind:       DiagnosticKind::Syntax,
+                pos:        at(pos),
+                message:    "Syntax ERROR (unmatched ')')".to_string(),
+                suggestion: Some("remove this ), or add a ( before it".to_string()),
+            });
+        }
+        let (tokens, spans) = tokenize_spanned(expr, self.ans_value(), self.si_suffixes, false)
+            .map_err(|(i, message)| Diagnostic {
+                kind:       DiagnosticKind::Syntax,
+                pos:        at(source_index(expr, i)),
+                message,
+                suggestion: None,
+            })?;
+        let pos_of = |k: usize| at(spans.get(k).map_or(expr.chars().count(), |&i| source_index(expr, i)));
+
+        let ast = parse_tokens_at(&tokens).map_err(|(k, message)| {
+            let kind = if message == EMPTY_INPUT {
+                DiagnosticKind::Empty
+            } else if k >= tokens.len() {
+                DiagnosticKind::Incomplete
+            } else if message.starts_with("Argument") {
+                DiagnosticKind::Argument
+            } else {
+                DiagnosticKind::Syntax
+            };
+            Diagnostic { kind, pos: pos_of(k), suggestion: suggest_fix(kind, &tokens, k), message }
+        })?;
+
+        // Argument counts are only checked when a call runs; check them here too
+        if let Some((call, name)) = bad_arity(&ast, &mut 0) {
+            let k = tokens.iter().enumerate()
+                .filter(|(_, t)| matches!(t, Token::Func(_)))
+                .nth(call)
+                .map_or(0, |(k, _)| k);
+            return Err(Diagnostic {
+                kind:       DiagnosticKind::Argument,
+                pos:        pos_of(k),
+                message:    "Argument ERROR".to_string(),
+                suggestion: Some(format!("{} takes {}", name, arity_text(name))),
+            });
+        }
+        Ok(())
+    }
+
+    /// Evaluation context over the current angle unit, memory and word size
+    pub fn context(&self) -> EvalContext<'_> {
+        EvalContext::new(self.angle, &self.memory, self.base_n.then_some(self.word_bits))
+    }
+
+    /// `expr` in the tokenizer's form; only the comma locale needs a copy
+    fn delocalize<'a>(&self, expr: &'a str) -> Cow<'a, str> {
+        if self.comma_decimal { Cow::Owned(from_comma_locale(expr)) } else { Cow::Borrowed(expr) }
+    }
+
+    /// Tokenize `expr` into `scratch.tokens`
+    fn lex(&self, expr: &str, scratch: &mut Scratch) -> Result<(), String> {
+        let expr = &*self.delocalize(expr);
+        if let Some(pos) = unmatched_close(expr) {
+            return Err(format!("Syntax ERROR (unmatched ')' at {})", pos + 1));
+        }
+        tokenize_into(expr, self.ans_value(), self.si_suffixes, false, scratch).map_err(|(_, e)| e)?;
+        // A dangling operator is dropped, as on the hardware: `5+` is 5
+        while scratch.tokens.last().is_some_and(is_dangling_operator) {
+            scratch.tokens.pop();
+        }
+        Ok(())
+    }
+
+    /// Tokenize + parse + eval; returns the value plus side effects for `evaluate` to apply
+    fn compute(&self, expr: &str, scratch: &mut Scratch) -> Result<Outcome, String> {
+        self.lex(expr, scratch)?;
+        let tokens = &scratch.tokens;
+        // Plain arithmetic on °'" values stays sexagesimal; sin(30°) does not
+        let dms = tokens.iter().any(|t| matches!(t, Token::Dms(_)))
+            && !tokens.iter().any(|t| matches!(t, Token::Func(_)));
+        let ast = parse_tokens(tokens)?;
+        let mut ctx = self.context();
+        let result = eval(&ast, &mut ctx)?;
+
+        if result.is_nan()      { return Err("Math ERROR".to_string()); }
+        if result.is_infinite() { return Err("Math ERROR (overflow)".to_string()); }
+
+        Ok(Outcome {
+            value:       result,
+            assigned:    ctx.assigned,
+            coords:      ctx.coords,
+            dms,
+            radian_like: ctx.radian_like,
+        })
+    }
+}
+
+/// Result of a parse, with the state changes `evaluate` commits on success
+struct Outcome {
+    value:    f64,
+    assigned: Vec<(char, f64)>,
+    coords:   Option<(f64, f64)>,
+    dms:      bool,
+    radian_like: bool,
+}
+
+// ─── Diagnostics ────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    /// Nothing to calculate
+    Empty,
+    /// The input stops mid-expression: `2+`, `sin(`
+    Incomplete,
+    /// A token where none can go: `sin)`, `1,2`
+    Syntax,
+    /// A function with a missing or wrong number of arguments
+    Argument,
+}
+
+/// Why `validate` rejected an expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind:       DiagnosticKind,
+    /// Char index into the expression where the problem is
+    pub pos:        usize,
+    /// The engine's error text
+    pub message:    String,
+    /// A fix in a few words, e.g. "missing ) before end"
+    pub suggestion: Option<String>,
 }
 
 // ─────────────────────────── FORMATTER ─────────────────────
 
-fn format_normal(val: f64) -> String {
+/// 2^53: above this not every integer has an f64
+const MAX_EXACT_INT: f64 = 9_007_199_254_740_992.0;
+
+fn needs_auto_sci(val: f64, upper: f64) -> bool {
+    let abs = val.abs();
+    val != 0.0 && (abs < SCI_LOWER || abs >= upper)
+}
+
+fn format_normal(val: f64, digits: usize, upper: f64) -> String {
     if val == 0.0 { return "0".to_string(); }
     let abs = val.abs();
 
-    if abs < 1e-9 || abs >= 1e10 {
-        return format_scientific(val, 9);
+    if needs_auto_sci(val, upper) {
+        return format_scientific(val, digits.saturating_sub(1));
     }
 
-    // Try integer first
-    if val == val.trunc() && abs < 1e15 {
-        return format!("{}", val as i64);
+    // Whole numbers print every digit while they fit the digit budget and
+    // f64 still holds them exactly; bigger ones (a raised sci_upper) go scientific
+    if val == val.trunc() {
+        if abs < 10f64.powi(digits as i32).min(MAX_EXACT_INT) {
+            return format!("{:.0}", val);
+        }
+        return format_scientific(val, digits.saturating_sub(1));
     }
 
-    // Up to 10 significant digits, trim trailing zeros
-    let s = format!("{:.10}", val);
+    // Up to `digits` significant digits, trim trailing zeros
+    let int_digits = abs.log10().floor() as i32 + 1;
+    let decimals = (digits as i32 - int_digits).max(0) as usize;
+    let s = format!("{:.prec$}", val, prec = decimals);
+    if decimals == 0 { return s; }
     let s = s.trim_end_matches('0').trim_end_matches('.');
     s.to_string()
 }
 
+/// `-12345.5` → `-12.345,5`: `.` groups the integer part, `,` is the decimal
+fn to_comma_locale(s: &str) -> String {
+    let (sign, body) = s.strip_prefix('-').map_or(("", s), |b| ("-", b));
+    let int_len = body.find(|c: char| !c.is_ascii_digit()).unwrap_or(body.len());
+    let (int, rest) = body.split_at(int_len);
+    let mut out = String::from(sign);
+    for (k, c) in int.chars().enumerate() {
+        if k > 0 && (int_len - k) % 3 == 0 { out.push('.'); }
+        out.push(c);
+    }
+    out.push_str(&rest.replace('.', ","));
+    out
+}
+
+/// Comma-locale input → the tokenizer's form: grouping `.` (and a space
+/// between digits) goes, `,` becomes the decimal point and `;` the argument separator
+fn from_comma_locale(expr: &str) -> String {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut out = String::with_capacity(expr.len());
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            _ if is_group_mark(&chars, i) => {}
+            ',' => out.push('.'),
+            ';' => out.push(','),
+            _   => out.push(c),
+        }
+    }
+    out
+}
+
+/// A grouping `.`, or a space between digits, that `from_comma_locale` drops
+fn is_group_mark(chars: &[char], i: usize) -> bool {
+    let digit = |i: Option<usize>| i.and_then(|i| chars.get(i)).is_some_and(|c| c.is_ascii_digit());
+    match chars[i] {
+        '.' => true,
+        ' ' => digit(i.checked_sub(1)) && digit(Some(i + 1)),
+        _   => false,
+    }
+}
+
+/// Char index in `expr` of char `pos` of `from_comma_locale(expr)`
+fn comma_source_index(expr: &str, pos: usize) -> usize {
+    let chars: Vec<char> = expr.chars().collect();
+    (0..chars.len()).filter(|&i| !is_group_mark(&chars, i)).nth(pos).unwrap_or(chars.len())
+}
+
 fn format_scientific(val: f64, prec: usize) -> String {
+    let s = format_sci_digits(val, prec + 1);
+    match s.split_once('×') {
+        Some((m, e)) if m.contains('.') => format!("{}×{}", m.trim_end_matches('0').trim_end_matches('.'), e),
+        _ => s,
+    }
+}
+
+/// Sci setup: exactly `digits` significant digits, trailing zeros kept (2.00×10^0)
+fn format_sci_digits(val: f64, digits: usize) -> String {
     if val == 0.0 { return "0".to_string(); }
-    let exp = val.abs().log10().floor() as i32;
-    let mantissa = val / 10f64.powi(exp);
-    let s = format!("{:.prec$}", mantissa, prec = prec);
-    let s = s.trim_end_matches('0').trim_end_matches('.');
-    format!("{}×10^{}", s, exp)
+    // `{:e}` rounds the decimal digits themselves: no val÷10^exp error in the
+    // last digit, and 9.999 rounding up lands in the next decade by itself
+    let s = format!("{:.prec$e}", val, prec = digits.saturating_sub(1));
+    let (mantissa, exp) = s.split_once('e').expect("{:e} has an exponent");
+    format!("{}×10^{}", mantissa, exp)
 }
 
 fn format_engineering(val: f64) -> String {
@@ -183,84 +734,387 @@ fn format_engineering(val: f64) -> String {
     format!("{:.3}×10^{}", mantissa, eng_exp)
 }
 
+// ─── Colours ────────────────────────────────────────────────
+
+/// Largest 24-bit colour value, 0xFFFFFF
+pub const MAX_COLOR: f64 = 16_777_215.0;
+
+/// A whole number in 0..=0xFFFFFF split into its red, green and blue bytes
+pub fn rgb_color(val: f64) -> Option<(u8, u8, u8)> {
+    if val.fract() != 0.0 || !(0.0..=MAX_COLOR).contains(&val) { return None; }
+    let v = val as u32;
+    Some(((v >> 16) as u8, (v >> 8) as u8, v as u8))
+}
+
+/// `rgb_color` written as `#RRGGBB`: 16744448 → #FF8000
+pub fn hex_color(val: f64) -> Option<String> {
+    rgb_color(val).map(|(r, g, b)| format!("#{:02X}{:02X}{:02X}", r, g, b))
+}
+
+// ─── Fractions ──────────────────────────────────────────────
+
+/// Largest denominator a b/c display will show
+pub const FRACTION_MAX_DEN: i64 = 10_000;
+/// Relative error below which a float counts as exactly n/d
+const FRACTION_TOLERANCE: f64 = 1e-10;
+/// Looser match for F↔D on typed/rounded decimals (√2's convergents miss it)
+const FRACTION_APPROX_TOLERANCE: f64 = 1e-9;
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 { (a, b) = (b, a % b); }
+    a
+}
+
+/// Best n/d (lowest terms, sign on n) within `tolerance` of `val` by continued
+/// fractions, or `None` if every convergent up to `max_den` misses
+fn to_fraction(val: f64, max_den: i64, tolerance: f64) -> Option<(i64, i64)> {
+    if !val.is_finite() || val.abs() >= 1e15 { return None; }
+    let target = val.abs();
+    let (mut h0, mut h1) = (0i64, 1i64);
+    let (mut k0, mut k1) = (1i64, 0i64);
+    let mut x = target;
+    loop {
+        let a = x.floor() as i64;
+        let h = a.checked_mul(h1)?.checked_add(h0)?;
+        let k = a.checked_mul(k1)?.checked_add(k0)?;
+        if k > max_den { return None; }
+        (h0, h1, k0, k1) = (h1, h, k1, k);
+        if (target - h as f64 / k as f64).abs() <= tolerance * target.max(1.0) { break; }
+        let frac = x - a as f64;
+        if frac == 0.0 { return None; }
+        x = 1.0 / frac;
+    }
+    let g = gcd(h1, k1);
+    let n = h1 / g;
+    Some((if val < 0.0 { -n } else { n }, k1 / g))
+}
+
+/// n/d as `n⌟d`, or `w⌟n⌟d` when `mixed` and |n/d| > 1; the sign leads
+fn format_fraction_parts(n: i64, d: i64, mixed: bool) -> String {
+    if d == 1 { return n.to_string(); }
+    let sign = if n < 0 { "-" } else { "" };
+    let n = n.abs();
+    if mixed && n > d {
+        format!("{}{}⌟{}⌟{}", sign, n / d, n % d, d)
+    } else {
+        format!("{}{}⌟{}", sign, n, d)
+    }
+}
+
 // ─────────────────────────── TOKENIZER ─────────────────────
 
-fn tokenize(
+/// Invisible direction/format marks that leak in from pasted (e.g. Hebrew) text
+fn is_invisible_mark(c: char) -> bool {
+    matches!(c,
+        '\u{200B}'..='\u{200F}' |   // zero-width space/joiners, LRM, RLM
+        '\u{202A}'..='\u{202E}' |   // bidi embeddings/overrides
+        '\u{2066}'..='\u{2069}' |   // bidi isolates
+        '\u{061C}' |                // Arabic letter mark
+        '\u{FEFF}'                  // BOM / zero-width no-break space
+    )
+}
+
+/// Fold full-width forms (０-９, ＋, （ …) and the other minus, times and
+/// divide glyphs (− · ∕ …) to ASCII, expand the natural-display superscripts
+/// ² ³ to ^2 ^3 and drop invisible marks
+pub(crate) fn normalize_input(input: &str) -> String {
+    let mut out = Vec::with_capacity(input.len());
+    normalize_into(input, &mut out);
+    out.into_iter().collect()
+}
+
+/// `normalize_input`, into a reused buffer
+fn normalize_into(input: &str, out: &mut Vec<char>) {
+    out.clear();
+    for c in input.chars().filter(|&c| !is_invisible_mark(c)) {
+        match c {
+            '\u{FF01}'..='\u{FF5E}' => out.push(char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)),
+            '\u{3000}' => out.push(' '),
+            '²' => out.extend(['^', '2']),
+            '³' => out.extend(['^', '3']),
+            // Minus, times and divide as pasted from other apps and fonts
+            '−' | '–' | '‒' | '﹣' => out.push('-'),
+            '·' | '⋅' | '∗' | '✕' => out.push('*'),
+            '∕' | '⁄' => out.push('/'),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Char index in `input` of char `pos` of `normalize_input(input)`
+fn source_index(input: &str, pos: usize) -> usize {
+    let mut width = 0;
+    for (i, c) in input.chars().enumerate() {
+        width += match c {
+            _ if is_invisible_mark(c) => 0,
+            '²' | '³' => 2,
+            _ => 1,
+        };
+        if width > pos { return i; }
+    }
+    input.chars().count()
+}
+
+/// Known function names (longest first to avoid prefix clash;
+/// e.g. `log₂` must stay ahead of `log` or `log₂(8)` reads as `log` then `₂`)
+pub(crate) const FUNCS: &[&str] = &[
+    "asinh","acosh","atanh","asin","acos","atan",
+    "sinh","cosh","tanh","sin","cos","tan",
+    "log₂","log","ln","sqrt","cbrt","abs","exp",
+    "floor","ceil","round","int","frac",
+    "nCr","nPr","Rec","Pol","percent","pctchange","markup","margin",
+    "max","min","ave",
+];
+
+/// Functions taking `(a, b)`
+const TWO_ARG_FUNCS: &[&str] = &["nCr","nPr","Rec","Pol","percent","pctchange","markup","margin"];
+
+/// Functions taking one or more arguments, `max(a, b, ...)`
+const VARIADIC_FUNCS: &[&str] = &["max","min","ave"];
+
+/// Char index of the first `)` that closes nothing. Missing `)` are fine:
+/// they close implicitly at the end, like on the real unit.
+pub fn unmatched_close(expr: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in expr.chars().enumerate() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Word and two-char operators (two-char forms before `<`, `>`, `=`)
+const WORD_OPERATORS: [(&str, Token); 11] = [
+    ("and", Token::And), ("xor", Token::Xor), ("or", Token::Or), ("not", Token::Not),
+    ("<<", Token::Shl), (">>", Token::Shr),
+    ("==", Token::Eq), ("<=", Token::Le), (">=", Token::Ge),
+    ("<", Token::Lt), (">", Token::Gt),
+];
+
+/// `chars[i..]` begins with `word`, checked in place; the tokenizer asks this
+/// at every position, so it must not build a String for the rest
+pub(crate) fn starts_with_at(chars: &[char], i: usize, word: &str) -> bool {
+    word.chars().enumerate().all(|(k, w)| chars.get(i + k) == Some(&w))
+}
+
+/// Multiplier for an SI suffix letter
+fn si_scale(c: char) -> Option<f64> {
+    match c {
+        'G'       => Some(1e9),
+        'M'       => Some(1e6),
+        'k'       => Some(1e3),
+        'm'       => Some(1e-3),
+        'µ' | 'μ' => Some(1e-6),
+        'n'       => Some(1e-9),
+        'p'       => Some(1e-12),
+        _         => None,
+    }
+}
+
+/// Separator in a b/c literals: 3⌟1⌟2 is 3½, 7⌟2 is 7/2
+const FRACTION_MARK: char = '⌟';
+
+/// Read the `⌟b` or `⌟b⌟c` that follows `first`; `i` points at the first mark
+fn read_fraction(chars: &[char], mut i: usize, first: f64) -> Result<(f64, usize), String> {
+    let mut parts = vec![first];
+    while parts.len() < 3 && chars.get(i) == Some(&FRACTION_MARK) {
+        let start = i + 1;
+        let mut end = start;
+        while end < chars.len() && chars[end].is_ascii_digit() { end += 1; }
+        if end == start { return Err("Syntax ERROR".to_string()); }
+        let s: String = chars[start..end].iter().collect();
+        parts.push(s.parse().map_err(|_| format!("Bad number: {}", s))?);
+        i = end;
+    }
+    let value = match parts[..] {
+        [n, d]    if d != 0.0 => n / d,
+        [w, n, d] if d != 0.0 => w + n / d,
+        _ => return Err("Math ERROR (div/0)".to_string()),
+    };
+    Ok((value, i))
+}
+
+/// Degree, minute and second marks, in the order they must appear
+const DMS_MARKS: [char; 3] = ['°', '\'', '"'];
+
+/// Fold `1°30'15"` (any trailing parts optional) into decimal degrees.
+/// `i` points at the mark after `first`; returns the value and the next index.
+fn read_dms(chars: &[char], mut i: usize, first: f64) -> (f64, usize) {
+    let mut total = 0.0;
+    let mut value = first;
+    let mut unit = 0;
+    while let Some(k) = DMS_MARKS[unit..].iter().position(|m| chars.get(i) == Some(m)) {
+        unit += k;
+        total += value / 60f64.powi(unit as i32);
+        i += 1;
+        unit += 1;
+        if unit == DMS_MARKS.len() { break; }
+
+        // The next part only counts if it carries a later mark
+        let start = i;
+        let mut end = i;
+        while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '.') { end += 1; }
+        if end == start || !DMS_MARKS[unit..].iter().any(|m| chars.get(end) == Some(m)) { break; }
+        let s: String = chars[start..end].iter().collect();
+        match s.parse() {
+            Ok(v) => value = v,
+            Err(_) => break,
+        }
+        i = end;
+    }
+    (total, i)
+}
+
+fn tokenize(input: &str, ans: f64, si: bool) -> Result<Vec<Token>, String> {
+    tokenize_spanned(input, ans, si, false).map(|(tokens, _)| tokens).map_err(|(_, e)| e)
+}
+
+/// Tokenizer buffers, reused across a batch so each expression does not
+/// allocate them afresh
+#[derive(Default)]
+struct Scratch {
+    chars:  Vec<char>,
+    tokens: Vec<Token>,
+    spans:  Vec<usize>,
+}
+
+/// `tokenize`, plus the char index (into the normalized input) each token
+/// starts at; an error carries the index it was found at. With `letters`,
+/// any other letter reads as a variable, for the LaTeX export's `sin(x)`.
+pub(crate) fn tokenize_spanned(
     input: &str,
     ans: f64,
-    memory: &HashMap<char, f64>,
-) -> Result<Vec<Token>, String> {
-    let mut tokens = Vec::new();
-    let chars: Vec<char> = input.chars().collect();
-    let mut i = 0;
+    si: bool,
+    letters: bool,
+) -> Result<(Vec<Token>, Vec<usize>), (usize, String)> {
+    let mut scratch = Scratch::default();
+    tokenize_into(input, ans, si, letters, &mut scratch)?;
+    Ok((scratch.tokens, scratch.spans))
+}
 
-    // Known function names (longest first to avoid prefix clash)
-    let funcs = [
-        "asinh","acosh","atanh","asin","acos","atan",
-        "sinh","cosh","tanh","sin","cos","tan",
-        "log₂","log","ln","sqrt","cbrt","abs","exp",
-        "nCr","nPr","Rec","Pol",
-    ];
+/// `tokenize_spanned` into `scratch`, clearing what the last run left there
+fn tokenize_into(
+    input: &str,
+    ans: f64,
+    si: bool,
+    letters: bool,
+    scratch: &mut Scratch,
+) -> Result<(), (usize, String)> {
+    let Scratch { chars, tokens, spans } = scratch;
+    normalize_into(input, chars);
+    let chars = &chars[..];
+    tokens.clear();
+    spans.clear();
+    let mut i = 0;
+    let mut here = 0;
 
     while i < chars.len() {
+        // Tokens pushed by the last pass start where that pass did
+        spans.resize(tokens.len(), here);
+        here = i;
         let c = chars[i];
 
         // Skip spaces
         if c == ' ' { i += 1; continue; }
 
+        // Hex / binary integer literals: 0xF0, 0b1010
+        if c == '0' && i + 1 < chars.len() && (chars[i+1] == 'x' || chars[i+1] == 'b') {
+            let radix = if chars[i+1] == 'x' { 16 } else { 2 };
+            let start = i + 2;
+            let mut end = start;
+            while end < chars.len() && chars[end].is_digit(radix) { end += 1; }
+            if end > start {
+                let s: String = chars[start..end].iter().collect();
+                let v = i64::from_str_radix(&s, radix).map_err(|_| (here, format!("Bad number: {}", s)))?;
+                tokens.push(Token::Number(v as f64));
+                i = end;
+                continue;
+            }
+        }
+
         // Number (including scientific notation: 1.5e3)
         if c.is_ascii_digit() || c == '.' {
             let start = i;
             while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
                 i += 1;
             }
-            // optional exponent
+            // optional exponent; a bare `e` after a number is the constant (2e = 2×e)
             if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
-                i += 1;
-                if i < chars.len() && (chars[i] == '+' || chars[i] == '-') { i += 1; }
-                while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') { j += 1; }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+                }
             }
             let s: String = chars[start..i].iter().collect();
-            let v: f64 = s.parse().map_err(|_| format!("Bad number: {}", s))?;
+            let v: f64 = s.parse().map_err(|_| (here, format!("Bad number: {}", s)))?;
+            if i < chars.len() && chars[i] == FRACTION_MARK {
+                let (frac, next) = read_fraction(chars, i, v).map_err(|e| (here, e))?;
+                tokens.push(Token::Number(frac));
+                i = next;
+                continue;
+            }
+            if i < chars.len() && DMS_MARKS.contains(&chars[i]) {
+                let (deg, next) = read_dms(chars, i, v);
+                tokens.push(Token::Dms(deg));
+                i = next;
+                continue;
+            }
+            // SI suffix glued to the literal; `2 M` and a lone `M` stay memory
+            if si && i < chars.len() && (i + 1 >= chars.len() || !chars[i+1].is_alphanumeric()) {
+                if let Some(scale) = si_scale(chars[i]) {
+                    tokens.push(Token::Number(v * scale));
+                    i += 1;
+                    continue;
+                }
+            }
             tokens.push(Token::Number(v));
             continue;
         }
 
         // Ans
-        if chars[i..].iter().collect::<String>().starts_with("Ans") {
-            tokens.push(Token::Number(ans));
+        if starts_with_at(chars, i, "Ans") {
+            tokens.push(Token::Const(ans));
             i += 3;
             continue;
         }
 
         // π and e constants
-        if c == 'π' { tokens.push(Token::Number(PI)); i += 1; continue; }
+        if c == 'π' { tokens.push(Token::Const(PI)); i += 1; continue; }
+        // ℯ (the e button) is never read as an exponent
+        if c == 'ℯ' { tokens.push(Token::Const(E)); i += 1; continue; }
         if c == 'e' && (i + 1 >= chars.len() || !chars[i+1].is_alphanumeric()) {
-            tokens.push(Token::Number(E));
+            tokens.push(Token::Const(E));
             i += 1;
             continue;
         }
 
         // Memory variables A..F X Y M
         if "ABCDEFXYMm".contains(c) && (i + 1 >= chars.len() || !chars[i+1].is_alphanumeric()) {
-            let key = c.to_ascii_uppercase();
-            tokens.push(Token::Number(*memory.get(&key).unwrap_or(&0.0)));
+            tokens.push(Token::Var(c.to_ascii_uppercase()));
             i += 1;
             continue;
         }
 
+        // Bitwise and comparison operators
+        if let Some((word, tok)) = WORD_OPERATORS.iter().find(|(w, _)| starts_with_at(chars, i, w)) {
+            tokens.push(tok.clone());
+            i += word.len();
+            continue;
+        }
+
         // Functions
-        let rest: String = chars[i..].iter().collect();
-        let mut matched = false;
-        for &fn_name in &funcs {
-            if rest.starts_with(fn_name) {
-                tokens.push(Token::Func(fn_name.to_string()));
-                i += fn_name.len();
-                matched = true;
-                break;
-            }
+        if let Some(&fn_name) = FUNCS.iter().find(|f| starts_with_at(chars, i, f)) {
+            tokens.push(Token::Func(fn_name.to_string()));
+            i += fn_name.chars().count();
+            continue;
         }
-        if matched { continue; }
 
         // Operators & punctuation
         match c {
@@ -274,26 +1128,61 @@ fn tokenize(
             ',' => tokens.push(Token::Comma),
             '!' => tokens.push(Token::Factorial),
             '%' => tokens.push(Token::Percent),
-            _ => return Err(format!("Unknown character: '{}'", c)),
+            '→' | '=' => tokens.push(Token::Assign),
+            // `√` after a value is the x√ root (`3√(8)` = 2), otherwise plain sqrt
+            '√' => match tokens.last() {
+                Some(Token::Number(_)) | Some(Token::Dms(_)) | Some(Token::Const(_)) | Some(Token::Var(_)) | Some(Token::RParen) => {
+                    tokens.push(Token::Root)
+                }
+                _ => tokens.push(Token::Func("sqrt".to_string())),
+            },
+            _ if letters && c.is_alphabetic() => tokens.push(Token::Var(c)),
+            _ => return Err((here, format!("Unknown character: '{}' (U+{:04X})", c, c as u32))),
         }
         i += 1;
     }
+    spans.resize(tokens.len(), here);
 
-    Ok(tokens)
+    Ok(())
+}
+
+// ─────────────────────────── AST ───────────────────────────
+
+/// A parsed expression. Parsing never evaluates; `eval` walks the tree,
+/// so one parse can be evaluated again and again (for a new X, say).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(char),
+    Neg(Box<Expr>),
+    /// Bitwise complement within the word size
+    Not(Box<Expr>),
+    Factorial(Box<Expr>),
+    Percent(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// `A=expr` / `expr→A`: the value of `expr`, also stored into the variable
+    Assign(char, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add, Sub, Mul, Div, Pow, Root,
+    And, Or, Xor, Shl, Shr,
+    Eq, Lt, Gt, Le, Ge,
 }
 
 // ─────────────────────────── PARSER ────────────────────────
-// Recursive descent: expr → term → power → unary → primary
+// Recursive descent: statement → expr → term → power → unary → primary
 
-struct Parser {
-    tokens: Vec<Token>,
+struct Parser<'t> {
+    tokens: &'t [Token],
     pos:    usize,
-    angle:  AngleMode,
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>, angle: AngleMode) -> Self {
-        Self { tokens, pos: 0, angle }
+impl<'t> Parser<'t> {
+    fn new(tokens: &'t [Token]) -> Self {
+        Self { tokens, pos: 0 }
     }
 
     fn peek(&self) -> Option<&Token> { self.tokens.get(self.pos) }
@@ -307,60 +1196,140 @@ impl Parser {
         }
     }
 
-    pub fn parse_expr(&mut self) -> Result<f64, String> {
-        self.parse_add_sub()
-    }
+    /// Top level: `A=expr` or `expr→A` (chainable, `5→A→B`); the value is the stored value
+    fn parse_statement(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Var(var)), Some(Token::Assign)) =
+            (self.tokens.get(self.pos).cloned(), self.tokens.get(self.pos + 1))
+        {
+            self.pos += 2;
+            let val = self.parse_statement()?;
+            return Ok(Expr::Assign(var, Box::new(val)));
+        }
 
-    fn parse_add_sub(&mut self) -> Result<f64, String> {
-        let mut left = self.parse_mul_div()?;
-        loop {
+        let mut val = self.parse_expr()?;
+        while self.peek() == Some(&Token::Assign) {
+            self.next();
             match self.peek() {
-                Some(Token::Plus)  => { self.next(); left += self.parse_mul_div()?; }
-                Some(Token::Minus) => { self.next(); left -= self.parse_mul_div()?; }
-                _ => break,
+                Some(&Token::Var(var)) => { self.next(); val = Expr::Assign(var, Box::new(val)) }
+                _ => return Err("Syntax ERROR".to_string()),
             }
         }
+        Ok(val)
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_comparison()
+    }
+
+    /// Left-associative run of `ops` over `operand`
+    fn parse_binary(
+        &mut self,
+        ops: &[(Token, BinOp)],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = operand(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(t, _)| self.peek() == Some(t)) {
+            self.next();
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
         Ok(left)
     }
 
-    fn parse_mul_div(&mut self) -> Result<f64, String> {
+    /// `a == b`, `a < b` … → 1 (true) or 0 (false)
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[
+            (Token::Eq, BinOp::Eq), (Token::Lt, BinOp::Lt), (Token::Gt, BinOp::Gt),
+            (Token::Le, BinOp::Le), (Token::Ge, BinOp::Ge),
+        ], Self::parse_or)
+    }
+
+    // Bitwise levels, loosest first: or → xor → and → shift → + −
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[(Token::Or, BinOp::Or)], Self::parse_xor)
+    }
+
+    fn parse_xor(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[(Token::Xor, BinOp::Xor)], Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[(Token::And, BinOp::And)], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[(Token::Shl, BinOp::Shl), (Token::Shr, BinOp::Shr)], Self::parse_add_sub)
+    }
+
+    /// Anything left over after a full expression is a syntax error —
+    /// commas are only valid as separators inside a function's argument list
+    fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None              => Ok(()),
+            Some(Token::Comma) => Err("Syntax ERROR (unexpected ,)".to_string()),
+            Some(Token::RParen) => Err("Syntax ERROR (unmatched ')')".to_string()),
+            Some(_)           => Err("Syntax ERROR".to_string()),
+        }
+    }
+
+    fn parse_add_sub(&mut self) -> Result<Expr, String> {
+        self.parse_binary(&[(Token::Plus, BinOp::Add), (Token::Minus, BinOp::Sub)], Self::parse_mul_div)
+    }
+
+    fn parse_mul_div(&mut self) -> Result<Expr, String> {
         let mut left = self.parse_power()?;
         loop {
-            match self.peek() {
-                Some(Token::Mul) => { self.next(); left *= self.parse_power()?; }
-                Some(Token::Div) => {
-                    self.next();
-                    let r = self.parse_power()?;
-                    if r == 0.0 { return Err("Math ERROR (div/0)".to_string()); }
-                    left /= r;
-                }
+            let op = match self.peek() {
+                Some(Token::Mul) => { self.next(); BinOp::Mul }
+                Some(Token::Div) => { self.next(); BinOp::Div }
+                // Implicit multiplication: 2π, 3sin(30), 2A, (1+2)(3+4);
+                // never between two literals, so `1 2` is an error
+                Some(Token::Const(_)) | Some(Token::Var(_))
+                | Some(Token::Func(_)) | Some(Token::LParen) => BinOp::Mul,
                 _ => break,
-            }
+            };
+            let right = self.parse_power()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
         }
         Ok(left)
     }
 
-    fn parse_power(&mut self) -> Result<f64, String> {
-        let base = self.parse_postfix()?;
-        if self.peek() == Some(&Token::Pow) {
+    /// Left to right, as on the hardware: `2^3^2` is (2^3)^2
+    fn parse_power(&mut self) -> Result<Expr, String> {
+        let mut base = self.parse_postfix()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Pow)  => BinOp::Pow,
+                Some(Token::Root) => BinOp::Root,
+                _ => return Ok(base),
+            };
             self.next();
-            let exp = self.parse_unary()?; // right-assoc
-            return Ok(base.powf(exp));
+            let exp = self.parse_exponent()?;
+            base = Expr::Binary(op, Box::new(base), Box::new(exp));
+        }
+    }
+
+    /// The operand of `^` or x√, which may carry a sign: `2^-2`
+    fn parse_exponent(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Minus) => { self.next(); Ok(Expr::Neg(Box::new(self.parse_exponent()?))) }
+            Some(Token::Plus)  => { self.next(); self.parse_exponent() }
+            _ => self.parse_postfix(),
         }
-        Ok(base)
     }
 
-    fn parse_postfix(&mut self) -> Result<f64, String> {
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
         let mut val = self.parse_unary()?;
         loop {
             match self.peek() {
                 Some(Token::Factorial) => {
                     self.next();
-                    val = factorial(val)?;
+                    val = Expr::Factorial(Box::new(val));
                 }
                 Some(Token::Percent) => {
                     self.next();
-                    val /= 100.0;
+                    val = Expr::Percent(Box::new(val));
                 }
                 _ => break,
             }
@@ -368,17 +1337,19 @@ impl Parser {
         Ok(val)
     }
 
-    fn parse_unary(&mut self) -> Result<f64, String> {
+    fn parse_unary(&mut self) -> Result<Expr, String> {
         match self.peek() {
-            Some(Token::Minus) => { self.next(); Ok(-self.parse_primary()?) }
-            Some(Token::Plus)  => { self.next(); self.parse_primary() }
+            Some(Token::Minus) => { self.next(); Ok(Expr::Neg(Box::new(self.parse_unary()?))) }
+            Some(Token::Plus)  => { self.next(); self.parse_unary() }
+            Some(Token::Not)   => { self.next(); Ok(Expr::Not(Box::new(self.parse_unary()?))) }
             _ => self.parse_primary(),
         }
     }
 
-    fn parse_primary(&mut self) -> Result<f64, String> {
+    fn parse_primary(&mut self) -> Result<Expr, String> {
         match self.next() {
-            Some(Token::Number(v)) => Ok(v),
+            Some(Token::Number(v)) | Some(Token::Dms(v)) | Some(Token::Const(v)) => Ok(Expr::Num(v)),
+            Some(Token::Var(c))    => Ok(Expr::Var(c)),
 
             Some(Token::LParen) => {
                 let v = self.parse_expr()?;
@@ -387,39 +1358,193 @@ impl Parser {
             }
 
             Some(Token::Func(name)) => {
-                // Expect '(' argument ')'
-                if self.peek() == Some(&Token::LParen) { self.next(); }
-                let arg = self.parse_expr()?;
-
-                // Two-arg functions: nCr, nPr, Rec, Pol
-                let result = if ["nCr","nPr","Rec","Pol"].contains(&name.as_str()) {
-                    if self.peek() == Some(&Token::Comma) { self.next(); }
-                    let arg2 = self.parse_expr()?;
-                    if self.peek() == Some(&Token::RParen) { self.next(); }
-                    apply_two_arg_func(&name, arg, arg2)?
-                } else {
-                    if self.peek() == Some(&Token::RParen) { self.next(); }
-                    self.apply_func(&name, arg)?
+                // Expect '(' arguments ')'; without the '(' only one argument
+                // is read, so `nCr(sin 30, 2)` keeps its comma. Each call
+                // collects its own commas: the inner calls of
+                // `nCr(max(5,3),min(4,2))` return at their ')' before the outer
+                // one sees the comma between them.
+                let paren = self.peek() == Some(&Token::LParen);
+                if paren { self.next(); }
+                self.expect_argument()?;
+                let mut args = vec![self.parse_expr()?];
+                while paren && self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    self.expect_argument()?;
+                    args.push(self.parse_expr()?);
+                }
+                if self.peek() == Some(&Token::RParen) { self.next(); }
+                Ok(Expr::Call(name, args))
+            }
+
+            other => {
+                // Leave `pos` on the offending token, for `validate`
+                if other.is_some() { self.pos -= 1; }
+                Err(format!("Unexpected token: {:?}", other))
+            }
+        }
+    }
+
+    /// A function argument can't be empty: `sin()`, `log(` or `nCr(5,)`
+    fn expect_argument(&self) -> Result<(), String> {
+        match self.peek() {
+            None | Some(Token::RParen) | Some(Token::Comma) => Err("Argument ERROR".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// ─────────────────────────── EVALUATOR ─────────────────────
+
+/// What `eval` reads (angle unit, memory, a bound X) and the side effects
+/// it records for `evaluate` to commit
+pub struct EvalContext<'a> {
+    pub angle:  AngleMode,
+    pub memory: &'a HashMap<char, f64>,
+    /// Word size in Base-N mode; None outside it, where bitwise operators fail
+    pub bits:   Option<u32>,
+    /// Value X reads as instead of its memory (integration, tables, solve)
+    pub x:      Option<f64>,
+    pub assigned: Vec<(char, f64)>,
+    pub coords: Option<(f64, f64)>,
+    /// A degree-mode trig call got a radian-looking argument
+    pub radian_like: bool,
+}
+
+impl<'a> EvalContext<'a> {
+    pub fn new(angle: AngleMode, memory: &'a HashMap<char, f64>, bits: Option<u32>) -> Self {
+        Self { angle, memory, bits, x: None, assigned: Vec::new(), coords: None, radian_like: false }
+    }
+
+    /// Variable value, seeing assignments made earlier in this expression
+    fn lookup(&self, var: char) -> f64 {
+        if var == 'X' {
+            if let Some(x) = self.x { return x; }
+        }
+        self.assigned.iter().rev()
+            .find(|(v, _)| *v == var)
+            .map(|(_, val)| *val)
+            .unwrap_or_else(|| *self.memory.get(&var).unwrap_or(&0.0))
+    }
+
+    /// Bitwise operands must be integers that fit the word, in Base-N mode
+    fn to_word(&self, v: f64) -> Result<i64, String> {
+        let Some(bits) = self.bits else { return Err("Math ERROR".to_string()) };
+        let limit = 2f64.powi(bits as i32);
+        if v != v.trunc() || v.abs() >= limit { return Err("Math ERROR".to_string()); }
+        Ok(v as i64)
+    }
+
+    /// Truncate to the word size and read back as two's complement
+    /// (only reached once `to_word` has accepted the operands)
+    fn wrap_word(&self, x: i64) -> f64 {
+        let shift = 64 - self.bits.unwrap_or(64);
+        ((x << shift) >> shift) as f64
+    }
+
+    fn apply_binary(&self, op: BinOp, left: f64, right: f64) -> Result<f64, String> {
+        match op {
+            BinOp::Add => Ok(left + right),
+            BinOp::Sub => Ok(left - right),
+            BinOp::Mul => Ok(left * right),
+            BinOp::Div => {
+                if right == 0.0 { return Err("Math ERROR (div/0)".to_string()); }
+                Ok(left / right)
+            }
+            BinOp::Pow => {
+                if left == 0.0 && right < 0.0 { return Err("Math ERROR (div/0)".to_string()); }
+                // Small integer powers by repeated multiply: 5^(-1) lands exactly on 0.2
+                if right == right.trunc() && right.abs() <= 64.0 {
+                    return Ok(left.powi(right as i32));
+                }
+                Ok(left.powf(right))
+            }
+            BinOp::Root => nth_root(left, right),
+            BinOp::And => Ok(self.wrap_word(self.to_word(left)? & self.to_word(right)?)),
+            BinOp::Or  => Ok(self.wrap_word(self.to_word(left)? | self.to_word(right)?)),
+            BinOp::Xor => Ok(self.wrap_word(self.to_word(left)? ^ self.to_word(right)?)),
+            BinOp::Shl | BinOp::Shr => {
+                let n = self.to_word(right)?;
+                if n < 0 || n >= self.bits.unwrap_or(64) as i64 { return Err("Math ERROR".to_string()); }
+                let x = self.to_word(left)?;
+                Ok(self.wrap_word(if op == BinOp::Shl { x << n } else { x >> n }))
+            }
+            // Equality allows float noise
+            BinOp::Eq | BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => {
+                let eq = approx_eq(left, right);
+                let holds = match op {
+                    BinOp::Eq => eq,
+                    BinOp::Lt => left < right && !eq,
+                    BinOp::Gt => left > right && !eq,
+                    BinOp::Le => left < right || eq,
+                    _         => left > right || eq,
                 };
+                Ok(if holds { 1.0 } else { 0.0 })
+            }
+        }
+    }
 
-                Ok(result)
+    fn apply_call(&mut self, name: &str, args: &[f64]) -> Result<f64, String> {
+        match *args {
+            _ if VARIADIC_FUNCS.contains(&name) => apply_variadic_func(name, args),
+            [a, b] if matches!(name, "Pol" | "Rec") => Ok(self.apply_coord_func(name, a, b)),
+            [a, b] if TWO_ARG_FUNCS.contains(&name) => apply_two_arg_func(name, a, b),
+            // log(base, x), as on the 991ES
+            [base, x] if name == "log" => {
+                if base <= 0.0 || base == 1.0 || x <= 0.0 { return Err("Math ERROR".to_string()); }
+                Ok(x.ln() / base.ln())
             }
+            [arg] if !TWO_ARG_FUNCS.contains(&name) => {
+                if matches!(name, "sin" | "cos" | "tan")
+                    && self.angle == AngleMode::Degrees
+                    && looks_like_radians(arg)
+                {
+                    self.radian_like = true;
+                }
+                self.apply_func(name, arg)
+            }
+            _ => Err("Argument ERROR".to_string()),
+        }
+    }
 
-            other => Err(format!("Unexpected token: {:?}", other)),
+    /// Pol(x, y) → r and Rec(r, θ) → x; the full pair is kept for display
+    fn apply_coord_func(&mut self, name: &str, a: f64, b: f64) -> f64 {
+        if name == "Pol" {
+            self.coords = Some((a, b));
+            a.hypot(b)
+        } else {
+            let theta = self.angle.to_rad(b);
+            let (x, y) = (a * theta.cos(), a * theta.sin());
+            self.coords = Some((x, y));
+            x
         }
     }
 
+    /// If `arg` is a whole number of quarter turns, that count mod 4
+    fn quarter_turns(&self, arg: f64) -> Option<i64> {
+        let q = arg / self.angle.quarter_turn();
+        if q.abs() > 1e9 || (q - q.round()).abs() > 1e-12 { return None; }
+        Some((q.round() as i64).rem_euclid(4))
+    }
+
     fn apply_func(&self, name: &str, arg: f64) -> Result<f64, String> {
         let r = self.angle.to_rad(arg);
         let ok = |v: f64| Ok(v);
 
+        // Exact multiples of a quarter turn (90°, 100ᵍ, π⁄2) give exact
+        // results instead of 1e-16 noise, in every angle unit
+        let quarter = self.quarter_turns(arg);
+
         match name {
-            "sin"   => ok(r.sin()),
-            "cos"   => ok(r.cos()),
-            "tan"   => {
-                if (r.cos()).abs() < 1e-12 { return Err("Math ERROR (tan undef)".to_string()); }
-                ok(r.tan())
-            }
+            "sin"   => ok(match quarter { Some(0) | Some(2) => 0.0, Some(1) => 1.0, Some(3) => -1.0, _ => r.sin() }),
+            "cos"   => ok(match quarter { Some(1) | Some(3) => 0.0, Some(0) => 1.0, Some(2) => -1.0, _ => r.cos() }),
+            "tan"   => match quarter {
+                Some(1) | Some(3) => Err("Math ERROR (tan undef)".to_string()),
+                Some(_)           => ok(0.0),
+                None => {
+                    if (r.cos()).abs() < 1e-12 { return Err("Math ERROR (tan undef)".to_string()); }
+                    ok(r.tan())
+                }
+            },
             "asin"  => {
                 if arg.abs() > 1.0 { return Err("Math ERROR".to_string()); }
                 ok(self.angle.from_rad(arg.asin()))
@@ -460,11 +1585,128 @@ impl Parser {
             "cbrt"  => ok(arg.cbrt()),
             "abs"   => ok(arg.abs()),
             "exp"   => ok(arg.exp()),
+            "floor" => ok(arg.floor()),
+            "ceil"  => ok(arg.ceil()),
+            "round" => ok(arg.round()),   // half away from zero, like the hardware
+            "int"   => ok(arg.trunc()),
+            "frac"  => ok(arg.fract()),
             _ => Err(format!("Unknown function: {}", name)),
         }
     }
 }
 
+/// Evaluate a parsed expression, recording assignments, Pol/Rec pairs and
+/// the DEG? hint in `ctx`
+pub fn eval(expr: &Expr, ctx: &mut EvalContext) -> Result<f64, String> {
+    match expr {
+        Expr::Num(v)       => Ok(*v),
+        Expr::Var(c)       => Ok(ctx.lookup(*c)),
+        Expr::Neg(e)       => Ok(-eval(e, ctx)?),
+        Expr::Not(e)       => {
+            let v = eval(e, ctx)?;
+            Ok(ctx.wrap_word(!ctx.to_word(v)?))
+        }
+        Expr::Factorial(e) => factorial(eval(e, ctx)?),
+        Expr::Percent(e)   => Ok(eval(e, ctx)? / 100.0),
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, ctx)?;
+            let right = eval(right, ctx)?;
+            ctx.apply_binary(*op, left, right)
+        }
+        Expr::Call(name, args) => {
+            let args = args.iter().map(|a| eval(a, ctx)).collect::<Result<Vec<_>, _>>()?;
+            ctx.apply_call(name, &args)
+        }
+        Expr::Assign(var, e) => {
+            let v = eval(e, ctx)?;
+            ctx.assigned.push((*var, v));
+            Ok(v)
+        }
+    }
+}
+
+/// Binary operators and `not`, which need an operand after them
+fn is_dangling_operator(t: &Token) -> bool {
+    matches!(t,
+        Token::Plus | Token::Minus | Token::Mul | Token::Div | Token::Pow | Token::Root
+        | Token::And | Token::Or | Token::Xor | Token::Not | Token::Shl | Token::Shr
+        | Token::Eq | Token::Lt | Token::Gt | Token::Le | Token::Ge)
+}
+
+/// A whole statement; tokens left over are a syntax error
+fn parse_tokens(tokens: &[Token]) -> Result<Expr, String> {
+    parse_tokens_at(tokens).map_err(|(_, e)| e)
+}
+
+/// `parse_tokens`, with the index of the token an error was found at
+/// (`tokens.len()` when the input ran out)
+fn parse_tokens_at(tokens: &[Token]) -> Result<Expr, (usize, String)> {
+    if tokens.is_empty() { return Err((0, EMPTY_INPUT.to_string())); }
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse_statement()
+        .and_then(|ast| parser.expect_end().map(|_| ast))
+        .map_err(|e| (parser.pos, e))?;
+    Ok(ast)
+}
+
+/// Fewest and most arguments `apply_call` accepts for `name` (None: no limit)
+fn arity(name: &str) -> (usize, Option<usize>) {
+    if VARIADIC_FUNCS.contains(&name) {
+        (1, None)
+    } else if TWO_ARG_FUNCS.contains(&name) {
+        (2, Some(2))
+    } else if name == "log" {
+        (1, Some(2))
+    } else {
+        (1, Some(1))
+    }
+}
+
+fn arity_text(name: &str) -> String {
+    match arity(name) {
+        (1, Some(1))               => "1 argument".to_string(),
+        (min, None)                => format!("{} or more arguments", min),
+        (min, Some(max)) if min == max => format!("{} arguments", min),
+        (min, Some(max))           => format!("{} or {} arguments", min, max),
+    }
+}
+
+/// The first call, by its index among the calls in source order, with an
+/// argument count `apply_call` would reject
+fn bad_arity<'e>(expr: &'e Expr, calls: &mut usize) -> Option<(usize, &'e str)> {
+    match expr {
+        Expr::Num(_) | Expr::Var(_) => None,
+        Expr::Neg(e) | Expr::Not(e) | Expr::Factorial(e) | Expr::Percent(e) | Expr::Assign(_, e) => {
+            bad_arity(e, calls)
+        }
+        Expr::Binary(_, l, r) => bad_arity(l, calls).or_else(|| bad_arity(r, calls)),
+        Expr::Call(name, args) => {
+            let index = *calls;
+            *calls += 1;
+            let (min, max) = arity(name);
+            if args.len() < min || max.is_some_and(|max| args.len() > max) {
+                return Some((index, name));
+            }
+            args.iter().find_map(|a| bad_arity(a, calls))
+        }
+    }
+}
+
+/// A few words on fixing the error at token `k`
+fn suggest_fix(kind: DiagnosticKind, tokens: &[Token], k: usize) -> Option<String> {
+    let hint = match (kind, tokens.get(k), tokens.last()) {
+        (DiagnosticKind::Empty, ..) => return None,
+        (DiagnosticKind::Argument, ..) => "a function argument is missing",
+        (DiagnosticKind::Incomplete, _, Some(t)) if is_dangling_operator(t) => "missing value after the operator",
+        (DiagnosticKind::Incomplete, _, Some(Token::Assign)) => "missing variable before end",
+        (DiagnosticKind::Incomplete, ..) => "missing value before end",
+        (_, Some(Token::Comma), _) => ", only separates function arguments",
+        (_, Some(Token::RParen), _) => "remove this ), or add a ( before it",
+        _ => "missing operator or value before this",
+    };
+    Some(hint.to_string())
+}
+
 fn apply_two_arg_func(name: &str, a: f64, b: f64) -> Result<f64, String> {
     match name {
         "nCr" => {
@@ -479,20 +1721,56 @@ fn apply_two_arg_func(name: &str, a: f64, b: f64) -> Result<f64, String> {
             if r > n { return Err("Math ERROR".to_string()); }
             Ok(permutations(n, r) as f64)
         }
-        "Rec" => {
-            // Rec(r, θ) → x = r·cos(θ), but we return x here; y shown separately
-            Ok(a * b.to_radians().cos())
+        "percent" => {
+            // percent(part, whole) → part as % of whole
+            if b == 0.0 { return Err("Math ERROR (div/0)".to_string()); }
+            Ok(a / b * 100.0)
+        }
+        "pctchange" => {
+            // pctchange(old, new) → % change from old to new
+            if a == 0.0 { return Err("Math ERROR (div/0)".to_string()); }
+            Ok((b - a) / a * 100.0)
+        }
+        "markup" => {
+            // markup(cost, pct) → selling price with pct% on top (also price + tax)
+            Ok(a * (1.0 + b / 100.0))
         }
-        "Pol" => {
-            // Pol(x, y) → r = √(x²+y²)
-            Ok((a * a + b * b).sqrt())
+        "margin" => {
+            // margin(price, cost) → gross profit as % of the selling price
+            if a == 0.0 { return Err("Math ERROR (div/0)".to_string()); }
+            Ok((a - b) / a * 100.0)
         }
         _ => Err(format!("Unknown 2-arg function: {}", name)),
     }
 }
 
+fn apply_variadic_func(name: &str, args: &[f64]) -> Result<f64, String> {
+    if args.is_empty() { return Err("Argument ERROR".to_string()); }
+    match name {
+        "max" => Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        "min" => Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+        "ave" => Ok(args.iter().sum::<f64>() / args.len() as f64),
+        _ => Err(format!("Unknown function: {}", name)),
+    }
+}
+
 // ─────────────────────────── HELPERS ───────────────────────
 
+/// A small argument within 0.01 of a multiple of π/4 or π/6 (3.14, 1.57,
+/// 0.785, 0.52 …): typed as radians while the calculator is in degrees
+pub fn looks_like_radians(arg: f64) -> bool {
+    if arg == 0.0 || arg.abs() > 4.0 * PI { return false; }
+    [PI / 4.0, PI / 6.0].iter().any(|step| {
+        let k = (arg / step).round();
+        k != 0.0 && (arg - k * step).abs() < 0.01
+    })
+}
+
+/// Equal up to float noise, so `sin(30)==0.5` holds
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-12 * a.abs().max(b.abs()).max(1.0)
+}
+
 fn factorial(n: f64) -> Result<f64, String> {
     if n < 0.0 || n != n.trunc() || n > 69.0 {
         return Err("Math ERROR".to_string());
@@ -502,6 +1780,18 @@ fn factorial(n: f64) -> Result<f64, String> {
     Ok(result as f64)
 }
 
+/// x√y: the `n`-th root of `x`, real for negative `x` when `n` is an odd integer
+fn nth_root(n: f64, x: f64) -> Result<f64, String> {
+    if n == 0.0 { return Err("Math ERROR".to_string()); }
+    if x < 0.0 {
+        if n == n.trunc() && (n as i64) % 2 != 0 {
+            return Ok(-(-x).powf(1.0 / n));
+        }
+        return Err("Math ERROR".to_string());
+    }
+    Ok(x.powf(1.0 / n))
+}
+
 fn combinations(n: u64, r: u64) -> u128 {
     if r == 0 || r == n { return 1; }
     let r = r.min(n - r);
@@ -518,3 +1808,770 @@ fn permutations(n: u64, r: u64) -> u128 {
     for i in 0..r { result *= (n - i) as u128; }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> f64 {
+        CalcEngine::new().try_evaluate(expr).unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn eval_in(angle: AngleMode, expr: &str) -> Result<f64, String> {
+        let mut e = CalcEngine::new();
+        e.angle = angle;
+        e.try_evaluate(expr)
+    }
+
+    #[test]
+    fn cube_and_cube_root_round_trip() {
+        assert_eq!(eval("cbrt(27)"), 3.0);
+        assert_eq!(eval("3^3"), 27.0);
+        assert_eq!(eval("3³"), 27.0);
+        assert!(close(eval("cbrt(5^3)"), 5.0));
+        assert!(close(eval("cbrt(2)^3"), 2.0));
+    }
+
+    #[test]
+    fn cube_root_of_negatives() {
+        assert_eq!(eval("cbrt(-8)"), -2.0);
+        assert_eq!(eval("(-2)^3"), -8.0);
+        assert!(close(eval("cbrt((-4)³)"), -4.0));
+    }
+
+    #[test]
+    fn memory_indicator_follows_m_plus_and_clear() {
+        let mut e = CalcEngine::new();
+        assert!(!e.memory_in_use());
+        e.m_plus_op(4.0);
+        assert!(e.memory_in_use());
+        e.m_minus_op(4.0);
+        assert!(!e.memory_in_use());
+        e.m_plus_op(1.5);
+        e.clear_m();
+        assert!(!e.memory_in_use());
+    }
+
+    #[test]
+    fn full_width_and_invisible_marks() {
+        assert_eq!(eval("２＋３"), 5.0);
+        assert_eq!(eval("1\u{200F}2+3"), 15.0);
+        assert_eq!(eval("\u{202B}7×2\u{202C}"), 14.0);
+    }
+
+    #[test]
+    fn unknown_character_names_its_code_point() {
+        let err = CalcEngine::new().try_evaluate("5\u{0301}+1").unwrap_err();
+        assert_eq!(err, "Unknown character: '\u{301}' (U+0301)");
+    }
+
+    #[test]
+    fn stray_comma_outside_a_call_is_an_error() {
+        assert_eq!(CalcEngine::new().try_evaluate("2,3").unwrap_err(), "Syntax ERROR (unexpected ,)");
+        assert_eq!(eval("nCr(5,2)"), 10.0);
+    }
+
+    #[test]
+    fn powers_chain_left_to_right() {
+        assert_eq!(eval("2^3^2"), 64.0);
+        assert_eq!(eval("2^(3^2)"), 512.0);
+        assert_eq!(eval("2^-2^2"), 0.0625);
+        assert_eq!(eval("5²²"), 625.0);
+        assert_eq!(eval("(2^3)^2"), 64.0);
+    }
+
+    /// An engine with every setup and memory field off its default
+    fn dirty_engine() -> CalcEngine {
+        let mut e = CalcEngine::new();
+        e.angle = AngleMode::Radians;
+        e.format = DisplayFormat::Fix(2);
+        e.fractions = true;
+        e.store('A', 5.0);
+        e.m_plus_op(3.0);
+        e.evaluate("7").unwrap();
+        e
+    }
+
+    #[test]
+    fn clear_setup_keeps_memory() {
+        let mut e = dirty_engine();
+        e.clear_setup();
+        assert_eq!(e.angle, AngleMode::Degrees);
+        assert_eq!(e.format, DisplayFormat::Normal);
+        assert!(!e.fractions);
+        assert_eq!(e.recall('A'), 5.0);
+        assert_eq!(e.recall_m(), 3.0);
+        assert_eq!(e.ans, 7.0);
+        assert_eq!(e.history.len(), 1);
+    }
+
+    #[test]
+    fn clear_memory_keeps_setup() {
+        let mut e = dirty_engine();
+        e.clear_memory();
+        assert_eq!(e.recall('A'), 0.0);
+        assert_eq!(e.recall_m(), 0.0);
+        assert_eq!(e.ans, 0.0);
+        assert_eq!(e.angle, AngleMode::Radians);
+        assert_eq!(e.format, DisplayFormat::Fix(2));
+        assert_eq!(e.history.len(), 1);
+    }
+
+    #[test]
+    fn clear_all_also_drops_history() {
+        let mut e = dirty_engine();
+        e.clear_all();
+        assert_eq!(e.angle, AngleMode::Degrees);
+        assert_eq!(e.recall('A'), 0.0);
+        assert!(e.history.is_empty());
+    }
+
+    #[test]
+    fn calls_without_arguments() {
+        let e = CalcEngine::new();
+        for expr in ["sin()", "log()", "nCr(5)", "sin(", "nCr(5,)"] {
+            assert_eq!(e.try_evaluate(expr).unwrap_err(), "Argument ERROR", "{}", expr);
+        }
+    }
+
+    #[test]
+    fn auto_sci_boundaries() {
+        let e = CalcEngine::new();
+        assert!(!e.is_auto_sci(9.9e9));
+        assert!(e.is_auto_sci(1e10));
+        assert!(!e.is_auto_sci(1e-9));
+        assert!(e.is_auto_sci(9e-10));
+        assert!(!e.is_auto_sci(0.0));
+        assert_eq!(e.format_result(9.9e9), "9900000000");
+        assert_eq!(e.format_result(1e10), "1×10^10");
+    }
+
+    #[test]
+    fn integer_parts_of_negatives() {
+        assert_eq!(eval("floor(-1.5)"), -2.0);
+        assert_eq!(eval("ceil(-1.5)"), -1.0);
+        assert_eq!(eval("round(-2.5)"), -3.0);
+        assert_eq!(eval("round(2.5)"), 3.0);
+        assert_eq!(eval("int(-1.7)"), -1.0);
+        assert!(close(eval("frac(-1.7)"), -0.7));
+    }
+
+    #[test]
+    fn history_evicts_oldest_first() {
+        let mut e = CalcEngine::new();
+        e.set_history_capacity(3);
+        for expr in ["1", "2", "3", "4"] {
+            e.evaluate(expr).unwrap();
+        }
+        let kept: Vec<f64> = e.history.iter().map(|(_, v)| *v).collect();
+        assert_eq!(kept, [2.0, 3.0, 4.0]);
+
+        e.set_history_capacity(1);
+        assert_eq!(e.history.len(), 1);
+        assert_eq!(e.history.back().unwrap().1, 4.0);
+
+        e.set_history_capacity(0);
+        e.evaluate("5").unwrap();
+        assert!(e.history.is_empty());
+    }
+
+    #[test]
+    fn percent_helpers() {
+        assert_eq!(eval("percent(25,200)"), 12.5);
+        assert_eq!(eval("pctchange(100,150)"), 50.0);
+        let e = CalcEngine::new();
+        assert_eq!(e.try_evaluate("percent(1,0)").unwrap_err(), "Math ERROR (div/0)");
+        assert_eq!(e.try_evaluate("pctchange(0,5)").unwrap_err(), "Math ERROR (div/0)");
+    }
+
+    #[test]
+    fn superscripts_normalize_to_powers() {
+        assert_eq!(normalize_input("5²"), "5^2");
+        assert_eq!(normalize_input("2³+1"), "2^3+1");
+        assert_eq!(eval("2³+1"), 9.0);
+    }
+
+    #[test]
+    fn assignment_stores_and_chains() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.evaluate("5→A").unwrap(), 5.0);
+        assert_eq!(e.evaluate("A×2").unwrap(), 10.0);
+        assert_eq!(e.evaluate("A=3").unwrap(), 3.0);
+        assert_eq!(e.evaluate("A^2").unwrap(), 9.0);
+        assert_eq!(e.evaluate("7→B→C").unwrap(), 7.0);
+        assert_eq!(e.recall('B'), 7.0);
+        assert_eq!(e.recall('C'), 7.0);
+    }
+
+    #[test]
+    fn antilogs_invert_logs() {
+        assert!(close(eval("10^(log(7))"), 7.0));
+        assert!(close(eval("exp(ln(5))"), 5.0));
+        assert!(close(eval("log(10^(2.5))"), 2.5));
+    }
+
+    #[test]
+    fn try_evaluate_leaves_state_alone() {
+        let mut e = CalcEngine::new();
+        e.evaluate("4").unwrap();
+        assert_eq!(e.try_evaluate("Ans×3").unwrap(), 12.0);
+        assert_eq!(e.try_evaluate("9→A").unwrap(), 9.0);
+        assert!(e.try_evaluate("1÷0").is_err());
+        assert_eq!(e.ans, 4.0);
+        assert_eq!(e.recall('A'), 0.0);
+        assert_eq!(e.history.len(), 1);
+    }
+
+    #[test]
+    fn evaluate_commits_what_try_evaluate_does_not() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.try_evaluate("2+5").unwrap(), 7.0);
+        assert_eq!(e.ans, 0.0);
+        assert!(e.history.is_empty());
+        assert_eq!(e.evaluate("2+5").unwrap(), 7.0);
+        assert_eq!(e.ans, 7.0);
+        assert_eq!(e.history.back(), Some(&("2+5".to_string(), 7.0)));
+    }
+
+    #[test]
+    fn inverse_trig_round_trips_in_every_unit() {
+        for (angle, right) in [(AngleMode::Degrees, 90.0), (AngleMode::Radians, PI / 2.0), (AngleMode::Gradians, 100.0)] {
+            assert!(close(eval_in(angle, &format!("sin({})", right)).unwrap(), 1.0), "{:?}", angle);
+            assert!(close(eval_in(angle, "asin(1)").unwrap(), right), "{:?}", angle);
+            assert!(close(eval_in(angle, "sin(asin(0.3))").unwrap(), 0.3), "{:?}", angle);
+            assert!(close(eval_in(angle, "tan(atan(2))").unwrap(), 2.0), "{:?}", angle);
+        }
+    }
+
+    #[test]
+    fn tan_is_undefined_at_a_quarter_turn() {
+        assert_eq!(eval_in(AngleMode::Gradians, "tan(100)").unwrap_err(), "Math ERROR (tan undef)");
+        assert_eq!(eval_in(AngleMode::Gradians, "tan(300)").unwrap_err(), "Math ERROR (tan undef)");
+        assert_eq!(eval_in(AngleMode::Degrees, "tan(90)").unwrap_err(), "Math ERROR (tan undef)");
+        assert!(close(eval_in(AngleMode::Gradians, "tan(50)").unwrap(), 1.0));
+    }
+
+    #[test]
+    fn variable_listing_is_stable() {
+        let mut e = CalcEngine::new();
+        e.store('X', 2.5);
+        e.store('A', 1.0);
+        e.m_plus_op(4.0);
+        let names: Vec<String> = e.variables().into_iter().map(|(n, _)| n).collect();
+        let mut want: Vec<String> = VARIABLES.iter().map(|c| c.to_string()).collect();
+        want.push("M+".to_string());
+        assert_eq!(names, want);
+        let vars = e.variables();
+        assert!(vars.contains(&("A".to_string(), 1.0)));
+        assert!(vars.contains(&("X".to_string(), 2.5)));
+        assert_eq!(vars.last(), Some(&("M+".to_string(), 4.0)));
+    }
+
+    #[test]
+    fn coordinates_in_polar_and_back() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.evaluate("Pol(3,4)").unwrap(), 5.0);
+        let (x, y) = e.coords.unwrap();
+        assert_eq!(e.format_coords(x, y), "(3,4)");
+        e.toggle_coord_form();
+        assert_eq!(e.format_coords(x, y), "5∠53.13010235°");
+
+        let theta = e.try_evaluate("atan(4÷3)").unwrap();
+        e.evaluate(&format!("Rec(5,{})", theta)).unwrap();
+        let (x, y) = e.coords.unwrap();
+        assert!(close(x, 3.0) && close(y, 4.0));
+    }
+
+    // ─── Display digits ───
+
+    #[test]
+    fn display_digits_set_the_significant_digits() {
+        let mut engine = CalcEngine::new();
+        engine.set_display_digits(10);
+        assert_eq!(engine.format_result(1.0 / 3.0), "0.3333333333");
+        assert_eq!(engine.format_result(123456789012.0), "1.23456789×10^11");
+        engine.set_display_digits(15);
+        assert_eq!(engine.format_result(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(engine.format_result(123456789012.0), "123456789012");
+    }
+
+    // ─── Bitwise ───
+
+    fn base_n() -> CalcEngine {
+        let mut e = CalcEngine::new();
+        e.base_n = true;
+        e
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_the_word() {
+        let mut e = base_n();
+        let mut eval = |expr| e.evaluate(expr).unwrap();
+        assert_eq!(eval("0xF0 and 0x0F"), 0.0);
+        assert_eq!(eval("0xF0 or 0x0F"), 255.0);
+        assert_eq!(eval("0xFF xor 0x0F"), 240.0);
+        assert_eq!(eval("1 << 4"), 16.0);
+        assert_eq!(eval("256 >> 4"), 16.0);
+        // Two's complement in 32 bits
+        assert_eq!(eval("not 0"), -1.0);
+        assert_eq!(eval("1 << 31"), -2147483648.0);
+        assert!(e.try_evaluate("1.5 and 1").is_err());
+        assert!(e.try_evaluate("1 << 32").is_err());
+    }
+
+    #[test]
+    fn bitwise_operators_need_base_n_mode() {
+        let e = CalcEngine::new();
+        for expr in ["3 and 1", "1 or 2", "5 xor 3", "not 0", "1 << 4", "16 >> 2"] {
+            assert_eq!(e.try_evaluate(expr), Err("Math ERROR".to_string()), "{}", expr);
+        }
+        assert_eq!(base_n().try_evaluate("3 and 1"), Ok(1.0));
+    }
+
+    #[test]
+    fn word_size_is_clamped() {
+        let mut e = base_n();
+        e.set_word_bits(0);
+        assert_eq!(e.try_evaluate("not 0"), Ok(-1.0));
+        assert!(e.try_evaluate("1 << 1").is_err());
+        e.set_word_bits(200);
+        assert_eq!(e.try_evaluate("1 << 63"), Ok(i64::MIN as f64));
+        e.set_word_bits(8);
+        assert_eq!(e.try_evaluate("0x7F + 1 or 0"), Ok(-128.0));
+    }
+
+    // ─── DMS ───
+
+    #[test]
+    fn dms_arithmetic_carries_and_borrows() {
+        let mut e = CalcEngine::new();
+        let v = e.evaluate("1°30'+2°45'").unwrap();
+        assert!(e.dms);
+        assert_eq!(e.format_dms(v), "4°15'0\"");
+        let v = e.evaluate("3°10'-1°20'30\"").unwrap();
+        assert_eq!(e.format_dms(v), "1°49'30\"");
+        let v = e.evaluate("0°0'59.999\"+0°0'0.001\"").unwrap();
+        assert_eq!(e.format_dms(v), "0°1'0\"");
+        e.evaluate("sin(30°)").unwrap();
+        assert!(!e.dms);
+    }
+
+    #[test]
+    fn log2_is_not_read_as_log() {
+        assert_eq!(eval("log₂(8)"), 3.0);
+        assert_eq!(eval("log(100)"), 2.0);
+        assert!(FUNCS.iter().position(|f| *f == "log₂") < FUNCS.iter().position(|f| *f == "log"));
+    }
+
+    // ─── Implicit multiplication ───
+
+    #[test]
+    fn constants_multiply_implicitly() {
+        assert!(close(eval("2π"), 2.0 * PI));
+        assert!(close(eval("πe"), PI * E));
+        assert!(close(eval("e^2"), E * E));
+        assert!(close(eval("2e"), 2.0 * E));
+        assert_eq!(eval("2(3+4)"), 14.0);
+    }
+
+    #[test]
+    fn two_literals_do_not_multiply() {
+        assert_eq!(CalcEngine::new().try_evaluate("1 2").unwrap_err(), "Syntax ERROR");
+        assert!(CalcEngine::new().try_evaluate("2 0.5").is_err());
+    }
+
+    // ─── Fractions ───
+
+    #[test]
+    fn fractions_reduce_and_mix() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.format_fraction(4.0 / 8.0).as_deref(), Some("1⌟2"));
+        assert_eq!(e.format_fraction(7.0 / 2.0).as_deref(), Some("3⌟1⌟2"));
+        assert_eq!(e.format_fraction(-6.0 / 4.0).as_deref(), Some("-1⌟1⌟2"));
+        e.mixed = false;
+        assert_eq!(e.format_fraction(7.0 / 2.0).as_deref(), Some("7⌟2"));
+        assert_eq!(e.format_fraction(-6.0 / 4.0).as_deref(), Some("-3⌟2"));
+        assert_eq!(e.format_fraction(PI), None);
+    }
+
+    // ─── Comparisons ───
+
+    #[test]
+    fn comparisons_are_one_or_zero() {
+        assert_eq!(eval("3==3"), 1.0);
+        assert_eq!(eval("3<2"), 0.0);
+        assert_eq!(eval("3>2"), 1.0);
+        assert_eq!(eval("2<=2"), 1.0);
+        assert_eq!(eval("1>=2"), 0.0);
+        // Float noise still counts as equal
+        assert_eq!(eval("0.1+0.2==0.3"), 1.0);
+        assert_eq!(eval("0.1+0.2<0.3"), 0.0);
+    }
+
+    // ─── SI suffixes ───
+
+    #[test]
+    fn si_suffixes_scale_literals() {
+        let mut e = CalcEngine::new();
+        e.si_suffixes = true;
+        assert!(close(e.try_evaluate("4.7k").unwrap(), 4700.0));
+        assert!(close(e.try_evaluate("1M").unwrap(), 1e6));
+        assert!(close(e.try_evaluate("2.2µ").unwrap(), 2.2e-6));
+        // A lone M, or one after a space, is still memory
+        e.store('M', 3.0);
+        assert_eq!(e.try_evaluate("M").unwrap(), 3.0);
+        assert_eq!(e.try_evaluate("2 M").unwrap(), 6.0);
+        e.si_suffixes = false;
+        assert_eq!(e.try_evaluate("1M").unwrap(), 3.0);
+    }
+
+    // ─── F↔D ───
+
+    #[test]
+    fn decimals_convert_to_close_fractions() {
+        let e = CalcEngine::new();
+        assert_eq!(e.approximate_fraction(0.75).as_deref(), Some("3⌟4"));
+        assert_eq!(e.approximate_fraction(0.333333333).as_deref(), Some("1⌟3"));
+        assert_eq!(e.approximate_fraction(2f64.sqrt()), None);
+        // The exact match does not take the rounded 0.333333333
+        assert_eq!(e.format_fraction(0.333333333), None);
+    }
+
+    // ─── Brackets ───
+
+    #[test]
+    fn unmatched_close_points_at_the_stray_bracket() {
+        assert_eq!(unmatched_close("2+3)"), Some(3));
+        assert_eq!(unmatched_close("(2+3))"), Some(5));
+        assert_eq!(unmatched_close("(2+3)"), None);
+        // Open brackets close themselves at the end
+        assert_eq!(unmatched_close("sin(30"), None);
+        assert_eq!(CalcEngine::new().try_evaluate("2+3)").unwrap_err(), "Syntax ERROR (unmatched ')' at 4)");
+    }
+
+    #[test]
+    fn evaluate_many_chains_ans() {
+        let mut e = CalcEngine::new();
+        let results = e.evaluate_many(&["2+3", "Ans×2", "1÷0", "Ans+1"]);
+        assert_eq!(results[0], Ok(5.0));
+        assert_eq!(results[1], Ok(10.0));
+        assert!(results[2].is_err());
+        // A failed line leaves Ans where it was
+        assert_eq!(results[3], Ok(11.0));
+        assert_eq!(e.ans, 11.0);
+    }
+
+    #[test]
+    fn scratch_buffers_start_clean() {
+        let mut scratch = Scratch::default();
+        tokenize_into("sin(30)+2×(4-1)", 0.0, false, false, &mut scratch).unwrap();
+        tokenize_into("7", 0.0, false, false, &mut scratch).unwrap();
+        assert_eq!(scratch.tokens, [Token::Number(7.0)]);
+        assert_eq!(scratch.spans, [0]);
+        let mut e = CalcEngine::new();
+        assert_eq!(e.evaluate_many(&["sin(30)+2×(4-1)", "7", "Ans"]), [Ok(6.5), Ok(7.0), Ok(7.0)]);
+    }
+
+    // ─── Tokenizer ───
+
+    #[test]
+    fn words_match_in_place() {
+        let chars: Vec<char> = "2+sin(30)".chars().collect();
+        assert!(starts_with_at(&chars, 2, "sin"));
+        assert!(!starts_with_at(&chars, 2, "sinh"));
+        assert!(!starts_with_at(&chars, 7, "30)"));
+    }
+
+    #[test]
+    fn long_expressions_tokenize() {
+        let expr = vec!["sin(1)"; 5000].join("+");
+        let tokens = tokenize(&expr, 0.0, false).unwrap();
+        assert_eq!(tokens.len(), 5000 * 4 + 4999);
+        let sum = vec!["sin(1)"; 200].join("+");
+        assert!(close(eval(&sum), 200.0 * 1f64.to_radians().sin()));
+    }
+
+    // ─── DEG? hint ───
+
+    #[test]
+    #[allow(clippy::approx_constant)] // 3.14 and 1.57 are the point
+    fn radian_looking_arguments() {
+        for arg in [3.14, 1.57, 0.785, 0.52, -1.57] {
+            assert!(looks_like_radians(arg), "{}", arg);
+        }
+        for arg in [0.0, 30.0, 45.0, 2.0, 100.0] {
+            assert!(!looks_like_radians(arg), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn radian_hint_is_opt_in() {
+        let mut e = CalcEngine::new();
+        e.evaluate("sin(3.14)").unwrap();
+        assert!(!e.deg_warning);
+        e.radian_hint = true;
+        e.evaluate("sin(3.14)").unwrap();
+        assert!(e.deg_warning);
+        e.evaluate("sin(30)").unwrap();
+        assert!(!e.deg_warning);
+    }
+
+    #[test]
+    fn negative_powers_land_on_fractions() {
+        let mut e = CalcEngine::new();
+        e.fractions = true;
+        let v = e.evaluate("5^(-1)").unwrap();
+        assert_eq!(v, 0.2);
+        assert_eq!(e.format_fraction(v).as_deref(), Some("1⌟5"));
+        let v = e.evaluate("(2/3)^(-1)").unwrap();
+        assert_eq!(e.format_fraction(v).as_deref(), Some("1⌟1⌟2"));
+        e.mixed = false;
+        assert_eq!(e.format_fraction(v).as_deref(), Some("3⌟2"));
+        assert_eq!(e.try_evaluate("0^(-1)").unwrap_err(), "Math ERROR (div/0)");
+    }
+
+    // ─── Comma locale ───
+
+    #[test]
+    fn comma_locale_reads_and_writes_decimal_commas() {
+        let mut e = CalcEngine::new();
+        e.comma_decimal = true;
+        assert_eq!(e.try_evaluate("3,14").unwrap(), 314.0 / 100.0);
+        assert_eq!(e.try_evaluate("nCr(5;2)").unwrap(), 10.0);
+        assert_eq!(e.try_evaluate("1.234,5+1 000").unwrap(), 2234.5);
+        assert_eq!(e.format_result(1234.5), "1.234,5");
+        assert_eq!(e.format_result(-0.25), "-0,25");
+    }
+
+    #[test]
+    fn markup_and_margin() {
+        assert!(close(eval("markup(80,25)"), 100.0));
+        assert!(close(eval("markup(100,-10)"), 90.0));
+        assert!(close(eval("margin(100,80)"), 20.0));
+        assert!(close(eval("margin(80,100)"), -25.0));
+        assert_eq!(CalcEngine::new().try_evaluate("margin(0,5)").unwrap_err(), "Math ERROR (div/0)");
+    }
+
+    // ─── Cache ───
+
+    #[test]
+    fn cache_keeps_recent_results() {
+        let e = CalcEngine::new();
+        assert_eq!(e.try_evaluate("2+2").unwrap(), 4.0);
+        assert_eq!(e.try_evaluate(" 2+2 ").unwrap(), 4.0);
+        assert_eq!(e.cache.borrow().len(), 1);
+        for i in 0..CACHE_CAPACITY + 5 {
+            e.try_evaluate(&i.to_string()).unwrap();
+        }
+        assert_eq!(e.cache.borrow().len(), CACHE_CAPACITY);
+        assert!(!e.cache.borrow().iter().any(|(k, _)| k.expr == "2+2"));
+    }
+
+    #[test]
+    fn cache_follows_the_state() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.try_evaluate("sin(90)").unwrap(), 1.0);
+        e.angle = AngleMode::Radians;
+        assert!(close(e.try_evaluate("sin(90)").unwrap(), 90f64.sin()));
+        e.store('A', 2.0);
+        assert_eq!(e.try_evaluate("A+1").unwrap(), 3.0);
+        e.store('A', 5.0);
+        assert_eq!(e.try_evaluate("A+1").unwrap(), 6.0);
+        e.evaluate("7").unwrap();
+        assert!(e.cache.borrow().is_empty());
+        assert_eq!(e.try_evaluate("Ans").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn ans_multiplies_like_a_constant() {
+        let mut e = CalcEngine::new();
+        e.evaluate("3").unwrap();
+        assert_eq!(e.try_evaluate("Ans+Ans").unwrap(), 6.0);
+        assert_eq!(e.try_evaluate("2Ans").unwrap(), 6.0);
+        assert_eq!(e.try_evaluate("AnsAns").unwrap(), 9.0);
+    }
+
+    // ─── Variadic functions ───
+
+    #[test]
+    fn max_min_ave_take_any_count() {
+        assert_eq!(eval("max(3,9,-2)"), 9.0);
+        assert_eq!(eval("min(3,9,-2)"), -2.0);
+        assert_eq!(eval("ave(1,2,3,4)"), 2.5);
+        assert_eq!(eval("max(7)"), 7.0);
+        assert_eq!(CalcEngine::new().try_evaluate("max()").unwrap_err(), "Argument ERROR");
+    }
+
+    #[test]
+    fn debug_tokens_lists_the_stream() {
+        let mut e = CalcEngine::new();
+        e.evaluate("4").unwrap();
+        assert_eq!(e.debug_tokens("2×sin(Ans)"), Ok(vec![
+            Token::Number(2.0), Token::Mul, Token::Func("sin".to_string()),
+            Token::LParen, Token::Const(4.0), Token::RParen,
+        ]));
+        e.comma_decimal = true;
+        assert_eq!(e.debug_tokens("1,5"), Ok(vec![Token::Number(1.5)]));
+        assert!(e.debug_tokens("2#").is_err());
+    }
+
+    // ─── AST ───
+
+    #[test]
+    fn parsed_tree_keeps_the_old_results() {
+        // Values from the evaluator before the AST layer; `^` chains left to right
+        let e = base_n();
+        for (expr, want) in [
+            ("2+3×4", 14.0), ("2^3^2", 64.0), ("-2²", 4.0), ("5!÷3", 40.0),
+            ("sin(30)+cos(60)", 1.0), ("nCr(5,2)", 10.0), ("√(16)", 4.0), ("50%", 0.5),
+            ("0xF0 and 0x3C", 48.0),
+        ] {
+            let tree = e.parse(expr).unwrap();
+            assert_eq!(super::eval(&tree, &mut e.context()), Ok(want), "{}", expr);
+            assert_eq!(e.try_evaluate(expr), Ok(want), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn parsed_tree_reevaluates_per_x() {
+        let e = CalcEngine::new();
+        let tree = e.parse("X²+1").unwrap();
+        let mut ctx = e.context();
+        let ys: Vec<f64> = (0..4).map(|x| {
+            ctx.x = Some(x as f64);
+            super::eval(&tree, &mut ctx).unwrap()
+        }).collect();
+        assert_eq!(ys, [1.0, 2.0, 5.0, 10.0]);
+        assert_eq!(tree, Expr::Binary(BinOp::Add,
+            Box::new(Expr::Binary(BinOp::Pow, Box::new(Expr::Var('X')), Box::new(Expr::Num(2.0)))),
+            Box::new(Expr::Num(1.0))));
+    }
+
+    // ─── Whole numbers ───
+
+    #[test]
+    fn whole_numbers_format_exactly_within_the_budget() {
+        let mut e = CalcEngine::new();
+        assert_eq!(e.format_result(1234567890.0), "1234567890");
+        assert_eq!(e.format_result(12345678901.0), "1.23456789×10^10");
+        assert_eq!(e.format_result(1e12), "1×10^12");
+        assert_eq!(e.format_result(9007199254740992.0), "9.007199255×10^15");
+        e.set_display_digits(16);
+        assert_eq!(e.format_result(12345678901.0), "12345678901");
+        assert_eq!(e.format_result(9007199254740991.0), "9007199254740991");
+        // 2^53 is past where every integer is exact, so it goes scientific;
+        // its last digit must survive that
+        assert_eq!(e.format_result(9007199254740992.0), "9.007199254740992×10^15");
+    }
+
+    // ─── Sci setup ───
+
+    #[test]
+    fn sci_keeps_the_chosen_digits() {
+        assert_eq!(format_sci_digits(123456.0, 3), "1.23×10^5");
+        assert_eq!(format_sci_digits(123456.0, 6), "1.23456×10^5");
+        assert_eq!(format_sci_digits(2.0, 3), "2.00×10^0");
+        assert_eq!(format_sci_digits(0.000998, 2), "1.0×10^-3");
+        assert_eq!(format_sci_digits(-9.9996, 4), "-1.000×10^1");
+        let mut e = CalcEngine::new();
+        e.format = DisplayFormat::Scientific(6);
+        assert_eq!(e.format_result(1.0 / 3.0), "3.33333×10^-1");
+    }
+
+    #[test]
+    fn nested_calls_split_their_own_commas() {
+        assert_eq!(eval("nCr(max(5,3),min(4,2))"), 10.0);
+        assert!(close(eval("log(abs(-2),8)"), 3.0));
+        assert!(close(eval("log(2,max(4,8))"), 3.0));
+    }
+
+    #[test]
+    fn format_full_shows_every_digit() {
+        let e = CalcEngine::new();
+        assert_eq!(e.format_full(1.0 / 3.0), "0.3333333333333333");
+        assert_eq!(e.format_full(2.0 / 7.0), "0.2857142857142857");
+        assert_eq!(e.format_result(2.0 / 7.0), "0.2857142857");
+        assert_eq!(e.format_full(1e-7), "1×10^-7");
+        assert_eq!(e.format_full(0.1 + 0.2), "0.30000000000000004");
+    }
+
+    #[test]
+    fn dangling_operators_drop_and_blank_input_is_empty() {
+        assert_eq!(eval("5+"), 5.0);
+        assert_eq!(eval("2×3÷"), 6.0);
+        assert_eq!(eval("0"), 0.0);
+        let e = CalcEngine::new();
+        assert_eq!(e.try_evaluate("").unwrap_err(), EMPTY_INPUT);
+        assert_eq!(e.try_evaluate("  ").unwrap_err(), EMPTY_INPUT);
+        assert_eq!(e.try_evaluate("+").unwrap_err(), EMPTY_INPUT);
+    }
+
+    #[test]
+    fn rounded_ans_uses_the_shown_value() {
+        for (rounded, want) in [(false, 1.0), (true, 0.99)] {
+            let mut e = CalcEngine::new();
+            e.format = DisplayFormat::Fix(2);
+            e.rounded_ans = rounded;
+            e.evaluate("1÷3").unwrap();
+            assert!(close(e.evaluate("Ans×3").unwrap(), want), "rounded {}", rounded);
+        }
+    }
+
+    // ─── Validate ───
+
+    #[test]
+    fn validate_places_the_problem() {
+        let e = CalcEngine::new();
+        assert_eq!(e.validate("2+3×sin(30)"), Ok(()));
+        let d = e.validate("2+").unwrap_err();
+        assert_eq!((d.kind, d.pos), (DiagnosticKind::Incomplete, 2));
+        assert_eq!(d.suggestion.as_deref(), Some("missing value after the operator"));
+        let d = e.validate("sin)").unwrap_err();
+        assert_eq!((d.kind, d.pos), (DiagnosticKind::Syntax, 3));
+        assert_eq!(d.suggestion.as_deref(), Some("remove this ), or add a ( before it"));
+        assert_eq!(e.validate("").unwrap_err().kind, DiagnosticKind::Empty);
+    }
+
+    #[test]
+    fn validate_positions_are_in_the_comma_locale_text() {
+        let mut e = CalcEngine::new();
+        e.comma_decimal = true;
+        // The grouping `.` and the digit-group space are dropped before
+        // tokenizing, but positions still point into the text as typed
+        let d = e.validate("1.000,5+)").unwrap_err();
+        assert_eq!(d.pos, 8);
+        let d = e.validate("1 000×sin)").unwrap_err();
+        assert_eq!(d.pos, 9);
+        let d = e.validate("1.000+").unwrap_err();
+        assert_eq!((d.kind, d.pos), (DiagnosticKind::Incomplete, 6));
+        let d = e.validate("2.500+$").unwrap_err();
+        assert_eq!((d.kind, d.pos), (DiagnosticKind::Syntax, 6));
+    }
+
+    // ─── Colour mode ───
+
+    #[test]
+    fn whole_numbers_in_range_are_colours() {
+        assert_eq!(rgb_color(16777215.0), Some((255, 255, 255)));
+        assert_eq!(rgb_color(0x12AB34 as f64), Some((0x12, 0xAB, 0x34)));
+        assert_eq!(hex_color(255.0).as_deref(), Some("#0000FF"));
+        assert_eq!(hex_color(0.0).as_deref(), Some("#000000"));
+        assert_eq!(hex_color(16777216.0), None);
+        assert_eq!(hex_color(-1.0), None);
+        assert_eq!(hex_color(1.5), None);
+    }
+
+    #[test]
+    fn operator_glyphs_read_like_ascii() {
+        assert_eq!(tokenize("5−3", 0.0, false), tokenize("5-3", 0.0, false));
+        assert_eq!(tokenize("5×3", 0.0, false), tokenize("5*3", 0.0, false));
+        assert_eq!(tokenize("6÷2", 0.0, false), tokenize("6/2", 0.0, false));
+        assert_eq!(eval("5−3"), 2.0);
+        assert_eq!(eval("5×3"), 15.0);
+        assert_eq!(eval("6÷2"), 3.0);
+    }
+}
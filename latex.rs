@@ -0,0 +1,271 @@
+// ============================================================
+//  latex.rs — Expression → LaTeX export
+//  Pure text transform; nothing here is evaluated
+// ============================================================
+
+// ─────────────────────────── TOKENS ────────────────────────
+
+use crate::engine::{normalize_input, tokenize_spanned, Token};
+
+/// The engine's tokens, each with the text it was read from, so literals
+/// print as typed (`π`, `7⌟2`, `Ans`) rather than as their values.
+/// The spans index the normalized text (`²` is two tokens, `^2`), so the
+/// slices come from that text and not from `input`.
+fn lex(input: &str) -> Result<Vec<(Token, String)>, String> {
+    let chars: Vec<char> = normalize_input(input).chars().collect();
+    let (tokens, spans) = tokenize_spanned(input, 0.0, false, true).map_err(|(_, e)| e)?;
+    let ends = spans.iter().skip(1).copied().chain([chars.len()]);
+    Ok(tokens.into_iter()
+        .zip(spans.iter().copied().zip(ends))
+        .map(|(t, (start, end))| (t, chars[start..end].iter().collect::<String>().trim().to_string()))
+        .collect())
+}
+
+/// A literal or variable as written: π → \pi, 7⌟2 → \frac{7}{2}, 30° → 30^\circ,
+/// 2E-5 → 2\times10^{-5}
+fn leaf_latex(src: &str) -> String {
+    if let Some((mantissa, exp)) = e_notation(src) {
+        return format!("{}\\times10^{{{}}}", mantissa, exp.trim_start_matches('+'));
+    }
+    match src {
+        "π"   => "\\pi".to_string(),
+        "ℯ"   => "e".to_string(),
+        "Ans" => "\\mathrm{Ans}".to_string(),
+        // a b/c literal: 7⌟2 → \frac{7}{2}, 3⌟1⌟2 → 3\frac{1}{2}
+        _ if src.contains('⌟') => match src.split('⌟').collect::<Vec<_>>()[..] {
+            [n, d]    => format!("\\frac{{{}}}{{{}}}", n, d),
+            [w, n, d] => format!("{}\\frac{{{}}}{{{}}}", w, n, d),
+            _         => src.to_string(),
+        },
+        _ => src.replace('°', "^\\circ").replace('"', "''"),
+    }
+}
+
+/// Mantissa and exponent of an E-notation literal (`2E-5`)
+fn e_notation(src: &str) -> Option<(&str, &str)> {
+    src.split_once(['e', 'E'])
+        .filter(|(mantissa, _)| !mantissa.is_empty() && mantissa.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
+// ─────────────────────────── WRITER ────────────────────────
+// Same precedence ladder as the engine parser, but every level
+// returns LaTeX source instead of a number.
+
+struct Writer {
+    toks: Vec<(Token, String)>,
+    pos:  usize,
+}
+
+impl Writer {
+    fn peek(&self) -> Option<&Token> { self.toks.get(self.pos).map(|(t, _)| t) }
+    fn next(&mut self) -> Option<(Token, String)> {
+        let t = self.toks.get(self.pos).cloned();
+        if t.is_some() { self.pos += 1; }
+        t
+    }
+
+    fn expr(&mut self) -> Result<String, String> {
+        let mut out = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus)  => '+',
+                Some(Token::Minus) => '-',
+                _ => break,
+            };
+            self.next();
+            out = format!("{}{}{}", out, op, self.term()?);
+        }
+        Ok(out)
+    }
+
+    fn term(&mut self) -> Result<String, String> {
+        let mut out = self.power()?;
+        loop {
+            match self.peek() {
+                Some(Token::Mul) => {
+                    self.next();
+                    out = format!("{} \\times {}", out, self.power()?);
+                }
+                Some(Token::Div) => {
+                    self.next();
+                    let den = self.power()?;
+                    out = format!("\\frac{{{}}}{{{}}}", strip_parens(&out), strip_parens(&den));
+                }
+                // Implicit multiplication: 2π, 3sin(x), (1+2)(3+4)
+                Some(Token::Const(_)) | Some(Token::Var(_))
+                | Some(Token::Func(_)) | Some(Token::LParen) => {
+                    out = format!("{}{}", out, self.power()?);
+                }
+                _ => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Left to right, as in the engine: `2^3^2` is {2^{3}}^{2}
+    fn power(&mut self) -> Result<String, String> {
+        let mut out = self.postfix()?;
+        let mut chained = false;
+        loop {
+            let pow = match self.peek() {
+                Some(Token::Pow)  => true,
+                Some(Token::Root) => false,
+                _ => return Ok(out),
+            };
+            self.next();
+            let operand = self.exponent()?;
+            out = if pow {
+                // A power raised again needs its base grouped
+                let base = if chained { format!("{{{}}}", out) } else { out };
+                format!("{}^{{{}}}", base, strip_parens(&operand))
+            } else {
+                format!("\\sqrt[{}]{{{}}}", out, strip_parens(&operand))
+            };
+            chained = true;
+        }
+    }
+
+    /// The operand of `^` or x√, which may carry a sign
+    fn exponent(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Minus) => { self.next(); Ok(format!("-{}", self.exponent()?)) }
+            Some(Token::Plus)  => { self.next(); self.exponent() }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<String, String> {
+        let mut out = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Factorial) => out.push('!'),
+                Some(Token::Percent)   => out.push_str("\\%"),
+                _ => break,
+            }
+            self.next();
+        }
+        Ok(out)
+    }
+
+    fn unary(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Minus) => { self.next(); Ok(format!("-{}", self.unary()?)) }
+            Some(Token::Plus)  => { self.next(); self.unary() }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some((Token::Number(_) | Token::Dms(_) | Token::Const(_) | Token::Var(_), src)) => Ok(leaf_latex(&src)),
+            Some((Token::LParen, _)) => {
+                let inner = self.expr()?;
+                if self.peek() == Some(&Token::RParen) { self.next(); }
+                Ok(format!("({})", inner))
+            }
+            Some((Token::Func(name), _)) => {
+                if self.peek() == Some(&Token::LParen) { self.next(); }
+                let mut args = vec![self.expr()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    args.push(self.expr()?);
+                }
+                if self.peek() == Some(&Token::RParen) { self.next(); }
+                Ok(func_latex(&name, &args))
+            }
+            other => Err(format!("Unexpected token: {:?}", other.map(|(t, _)| t))),
+        }
+    }
+}
+
+fn func_latex(name: &str, args: &[String]) -> String {
+    let a = &args[0];
+    let b = args.get(1).map(String::as_str).unwrap_or("");
+    match name {
+        "sqrt"  => format!("\\sqrt{{{}}}", a),
+        "cbrt"  => format!("\\sqrt[3]{{{}}}", a),
+        "abs"   => format!("\\left|{}\\right|", a),
+        "floor" => format!("\\lfloor {} \\rfloor", a),
+        "ceil"  => format!("\\lceil {} \\rceil", a),
+        "exp"   => format!("e^{{{}}}", a),
+        "log₂"  => format!("\\log_{{2}}({})", a),
+        "log" if args.len() == 2 => format!("\\log_{{{}}}({})", a, b),
+        "nCr"   => format!("\\binom{{{}}}{{{}}}", a, b),
+        "nPr"   => format!("{{}}_{{{}}}P_{{{}}}", a, b),
+        "sin" | "cos" | "tan" | "sinh" | "cosh" | "tanh" | "log" | "ln" => {
+            format!("\\{}({})", name, a)
+        }
+        "asin" | "acos" | "atan" => format!("\\{}^{{-1}}({})", &name[1..], a),
+        _ => format!("\\operatorname{{{}}}({})", name, args.join(",")),
+    }
+}
+
+/// Drop one pair of parentheses if they wrap the whole string
+fn strip_parens(s: &str) -> &str {
+    if !(s.starts_with('(') && s.ends_with(')')) { return s; }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 && i != s.len() - 1 { return s; }
+            }
+            _ => {}
+        }
+    }
+    &s[1..s.len() - 1]
+}
+
+// ─────────────────────────── API ───────────────────────────
+
+/// Convert a calculator expression to LaTeX, e.g. `sqrt(2)/3` → `\frac{\sqrt{2}}{3}`
+pub fn to_latex(expr: &str) -> Result<String, String> {
+    let mut w = Writer { toks: lex(expr)?, pos: 0 };
+    let out = w.expr()?;
+    if w.peek().is_some() {
+        return Err("Syntax ERROR".to_string());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractions_and_functions() {
+        assert_eq!(to_latex("sqrt(2)/3").unwrap(), "\\frac{\\sqrt{2}}{3}");
+        assert_eq!(to_latex("sin(x)^2").unwrap(), "\\sin(x)^{2}");
+    }
+
+    #[test]
+    fn literals_print_as_typed() {
+        assert_eq!(to_latex("2π").unwrap(), "2\\pi");
+        assert_eq!(to_latex("3⌟1⌟2").unwrap(), "3\\frac{1}{2}");
+        assert_eq!(to_latex("Ans×2").unwrap(), "\\mathrm{Ans} \\times 2");
+        assert_eq!(to_latex("30°").unwrap(), "30^\\circ");
+        assert_eq!(to_latex("1e3").unwrap(), "1\\times10^{3}");
+        assert_eq!(to_latex("2E-5").unwrap(), "2\\times10^{-5}");
+        assert_eq!(to_latex("0xE0").unwrap(), "0xE0");
+    }
+
+    #[test]
+    fn nested_calls_and_implicit_products() {
+        assert_eq!(to_latex("nCr(5,abs(-2))").unwrap(), "\\binom{5}{\\left|-2\\right|}");
+        assert_eq!(to_latex("3√(8)").unwrap(), "\\sqrt[3]{8}");
+        assert_eq!(to_latex("(1+2)(3+4)").unwrap(), "(1+2)(3+4)");
+        assert_eq!(to_latex("2^3^2").unwrap(), "{2^{3}}^{2}");
+        assert_eq!(to_latex("2^(3^2)").unwrap(), "2^{3^{2}}");
+        assert!(to_latex("2+3)").is_err());
+        // Missing `)` close at the end, as in the evaluator
+        assert_eq!(to_latex("sin(2").unwrap(), "\\sin(2)");
+        assert_eq!(to_latex("sqrt(2+3").unwrap(), "\\sqrt{2+3}");
+    }
+
+    #[test]
+    fn constants_stay_implicit_products() {
+        assert_eq!(to_latex("2π").unwrap(), "2\\pi");
+        assert!(to_latex("1 2").is_err());
+    }
+}
@@ -0,0 +1,25 @@
+// ============================================================
+//  lib.rs — CASIO calculator library
+//  Headless math engine + egui front-end, usable without main
+// ============================================================
+
+pub mod cli;
+pub mod engine;
+pub mod latex;
+pub mod macros;
+pub mod models;
+pub mod ui;
+
+pub use engine::{AngleMode, CalcEngine, CoordForm, DisplayFormat};
+
+/// Evaluate an expression with a fresh engine (degrees, normal format)
+pub fn eval(expr: &str) -> Result<f64, String> {
+    CalcEngine::new().try_evaluate(expr)
+}
+
+/// Evaluate an expression and format it the way the display would
+pub fn eval_formatted(expr: &str) -> Result<String, String> {
+    let engine = CalcEngine::new();
+    let val = engine.try_evaluate(expr)?;
+    Ok(engine.format_result(val))
+}
@@ -0,0 +1,183 @@
+// ============================================================
+//  macros.rs — Saved formulas with ? placeholders
+//  One `name=template` per line in ~/.casio-calc-macros
+// ============================================================
+
+use std::path::{Path, PathBuf};
+
+/// A named formula; `?` takes the next value, `?1`..`?9` a specific one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name:     String,
+    pub template: String,
+}
+
+impl Macro {
+    pub fn new(name: &str, template: &str) -> Self {
+        Self { name: name.to_string(), template: template.to_string() }
+    }
+
+    /// How many values the template asks for
+    pub fn placeholders(&self) -> usize {
+        let mut next = 0;
+        let mut count = 0;
+        for slot in parse_slots(&self.template) {
+            let index = slot.unwrap_or_else(|| { next += 1; next });
+            count = count.max(index);
+        }
+        count
+    }
+
+    /// Fill the placeholders, each value wrapped in parentheses so `-2` or
+    /// `1+1` substitute as a unit
+    pub fn expand(&self, values: &[String]) -> Result<String, String> {
+        if values.len() != self.placeholders() {
+            return Err("Argument ERROR".to_string());
+        }
+        let chars: Vec<char> = self.template.chars().collect();
+        let mut out = String::new();
+        let mut next = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '?' {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            let index = match chars.get(i + 1).and_then(|c| c.to_digit(10)) {
+                Some(d) if d > 0 => { i += 2; d as usize }
+                _                => { i += 1; next += 1; next }
+            };
+            out.push('(');
+            out.push_str(&values[index - 1]);
+            out.push(')');
+        }
+        Ok(out)
+    }
+}
+
+/// Slot of each `?` in order: `Some(n)` for `?n`, `None` for a bare `?`
+fn parse_slots(template: &str) -> Vec<Option<usize>> {
+    let chars: Vec<char> = template.chars().collect();
+    chars.iter().enumerate()
+        .filter(|(_, &c)| c == '?')
+        .map(|(i, _)| chars.get(i + 1).and_then(|c| c.to_digit(10)).filter(|&d| d > 0).map(|d| d as usize))
+        .collect()
+}
+
+/// Shipped until the user saves their own
+pub fn default_macros() -> Vec<Macro> {
+    vec![
+        Macro::new("quad", "(-?2+√(?2²-4?1?3))÷(2?1)"),
+        Macro::new("hyp",  "√(?²+?²)"),
+        Macro::new("tax",  "markup(?,?)"),
+    ]
+}
+
+// ─── Built-in formulas ──────────────────────────────────────
+
+/// A fixed formula over memory variables; CALC asks for each one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Formula {
+    pub name: &'static str,
+    pub expr: &'static str,
+}
+
+/// The ALPHA+CALC (FORMULA) menu, in order
+pub const FORMULAS: [Formula; 4] = [
+    // Larger root of Ax²+Bx+C
+    Formula { name: "quad",     expr: "(-B+√(B²-4×A×C))÷(2A)" },
+    // Between (A, B) and (X, Y)
+    Formula { name: "dist",     expr: "√((X-A)²+(Y-B)²)" },
+    // Radius A
+    Formula { name: "circle",   expr: "πA²" },
+    // Principal A at B% a period for C periods
+    Formula { name: "interest", expr: "A(1+B÷100)^C" },
+];
+
+// ─── Persistence ────────────────────────────────────────────
+
+pub fn macros_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".casio-calc-macros"))
+}
+
+/// `name=template` lines; blank lines and `#` comments are skipped
+pub fn parse_macros(text: &str) -> Vec<Macro> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, template)| Macro::new(name.trim(), template.trim()))
+        .collect()
+}
+
+pub fn format_macros(macros: &[Macro]) -> String {
+    macros.iter().map(|m| format!("{}={}\n", m.name, m.template)).collect()
+}
+
+/// The saved macros, or the defaults when nothing has been saved yet
+pub fn load_macros() -> Vec<Macro> {
+    macros_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|text| parse_macros(&text))
+        .filter(|macros| !macros.is_empty())
+        .unwrap_or_else(default_macros)
+}
+
+pub fn save_macros_to(path: &Path, macros: &[Macro]) -> Result<(), String> {
+    std::fs::write(path, format_macros(macros)).map_err(|e| e.to_string())
+}
+
+/// `m1`, `m2` … : the first name no macro has taken yet
+pub fn next_macro_name(macros: &[Macro]) -> String {
+    (1..).map(|n| format!("m{}", n))
+        .find(|name| !macros.iter().any(|m| &m.name == name))
+        .expect("unbounded range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_count_bare_and_numbered_slots() {
+        assert_eq!(Macro::new("hyp", "√(?²+?²)").placeholders(), 2);
+        assert_eq!(Macro::new("quad", "(-?2+√(?2²-4?1?3))÷(2?1)").placeholders(), 3);
+        assert_eq!(Macro::new("pi", "π").placeholders(), 0);
+    }
+
+    #[test]
+    fn expand_wraps_each_value() {
+        let hyp = Macro::new("hyp", "√(?²+?²)");
+        assert_eq!(hyp.expand(&["3".to_string(), "-4".to_string()]), Ok("√((3)²+(-4)²)".to_string()));
+        let sq = Macro::new("sq", "?1×?1");
+        assert_eq!(sq.expand(&["1+1".to_string()]), Ok("(1+1)×(1+1)".to_string()));
+        assert_eq!(hyp.expand(&["3".to_string()]), Err("Argument ERROR".to_string()));
+    }
+
+    #[test]
+    fn macros_round_trip_through_text() {
+        let text = "# saved\nhyp = √(?²+?²)\n\nbad line\ntax=markup(?,?)\n";
+        let macros = parse_macros(text);
+        assert_eq!(macros, vec![Macro::new("hyp", "√(?²+?²)"), Macro::new("tax", "markup(?,?)")]);
+        assert_eq!(format_macros(&macros), "hyp=√(?²+?²)\ntax=markup(?,?)\n");
+        assert_eq!(parse_macros(&format_macros(&macros)), macros);
+    }
+
+    #[test]
+    fn new_macros_take_the_first_free_name() {
+        assert_eq!(next_macro_name(&default_macros()), "m1");
+        let taken = [Macro::new("m1", "?"), Macro::new("m3", "?")];
+        assert_eq!(next_macro_name(&taken), "m2");
+    }
+
+    #[test]
+    fn save_writes_the_macro_file() {
+        let path = std::env::temp_dir().join(format!("casio-calc-macros-{}", std::process::id()));
+        let macros = [Macro::new("m1", "?×2")];
+        save_macros_to(&path, &macros).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(parse_macros(&text), macros);
+    }
+}
@@ -4,18 +4,26 @@
 //  Author: 56tytt — שי קדוש הנדסת תוכנה אשקלון
 // ============================================================
 
-mod engine;
-mod models;
-mod ui;
-
+use casio_calc::cli;
+use casio_calc::ui::{self, window_size, AppConfig};
 use eframe::egui;
-use models::ModelType;
 
 fn main() -> Result<(), eframe::Error> {
+    // `--eval EXPR` prints the result and exits without opening a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse_args(&args) {
+        Ok(Some(eval)) => match cli::run(&eval) {
+            Ok(out) => { println!("{}", out); std::process::exit(0); }
+            Err(e)  => { eprintln!("{}", e); std::process::exit(1); }
+        },
+        Ok(None) => {}
+        Err(e) => { eprintln!("{}", e); std::process::exit(2); }
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
         .with_title("CASIO Scientific Calculator")
-        .with_inner_size([400.0, 750.0])
+        .with_inner_size(window_size(AppConfig::default().model))
         .with_resizable(true)
         .with_min_inner_size([340.0, 600.0]),
         ..Default::default()
@@ -24,6 +32,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "CASIO Calculator",
         options,
-        Box::new(|cc| Box::new(ui::CasioApp::new(cc, ModelType::Fx82MS))),
+        Box::new(|cc| Box::new(ui::CasioApp::with_config(cc, AppConfig::default()))),
     )
 }
@@ -2,9 +2,11 @@
 //  models.rs — 3 CASIO Models: fx-82MS | fx-991ES | fx-CG50
 // ============================================================
 
-use egui::Color32;
+use egui::{Color32, Stroke};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::engine::AngleMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ModelType {
     Fx82MS,       // Pink/classic — S-V.P.A.M
     Fx991ES,      // Blue/grey   — Natural VPAM
@@ -20,6 +22,27 @@ impl ModelType {
         }
     }
 
+    /// Natural display models show x² / x³ as superscripts while typing
+    pub fn natural_display(self) -> bool {
+        matches!(self, ModelType::Fx991ES | ModelType::FxCG50)
+    }
+
+    /// Significant digits the display shows (the CG50's screen is wider)
+    pub fn digits(self) -> usize {
+        match self {
+            ModelType::Fx82MS | ModelType::Fx991ES => crate::engine::DISPLAY_DIGITS,
+            ModelType::FxCG50                      => 15,
+        }
+    }
+
+    /// Angle unit on first use; the CG50 graphs trig in radians
+    pub fn default_angle(self) -> AngleMode {
+        match self {
+            ModelType::Fx82MS | ModelType::Fx991ES => AngleMode::Degrees,
+            ModelType::FxCG50                      => AngleMode::Radians,
+        }
+    }
+
     pub fn subtitle(self) -> &'static str {
         match self {
             ModelType::Fx82MS  => "S-V.P.A.M.  2nd edition",
@@ -49,6 +72,8 @@ pub struct Palette {
     pub shadow_text:  Color32,
     pub border:       Color32,
     pub casio_text:   Color32,
+    /// Keys also tell their role apart by outline and a corner mark
+    pub accessible:   bool,
 }
 
 impl Palette {
@@ -72,6 +97,7 @@ impl Palette {
                 shadow_text:  Color32::from_rgb(255, 200, 100),
                 border:       Color32::from_rgb(130, 90, 90),
                 casio_text:   Color32::WHITE,
+                accessible:   false,
             },
             ModelType::Fx991ES => Self {
                 body:         Color32::from_rgb(138, 150, 185),
@@ -91,6 +117,7 @@ impl Palette {
                 shadow_text:  Color32::from_rgb(255, 200, 80),
                 border:       Color32::from_rgb(50, 60, 90),
                 casio_text:   Color32::WHITE,
+                accessible:   false,
             },
             ModelType::FxCG50 => Self {
                 body:         Color32::from_rgb(30, 30, 35),
@@ -110,9 +137,15 @@ impl Palette {
                 shadow_text:  Color32::from_rgb(100, 200, 255),
                 border:       Color32::from_rgb(20, 20, 28),
                 casio_text:   Color32::WHITE,
+                accessible:   false,
             },
         }
     }
+
+    /// The model's colours, with role outlines and marks that don't rely on hue
+    pub fn accessible(model: ModelType) -> Self {
+        Self { accessible: true, ..Self::for_model(model) }
+    }
 }
 
 // ─── Button definitions per model ──────────────────────────
@@ -128,6 +161,35 @@ pub struct BtnDef {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BtnColor { Num, Op, Fn, Ctrl, Eq, Del, Ac, Shift, Alpha }
 
+impl BtnColor {
+    /// Outline in the accessible palette: every role has its own width and
+    /// light/dark pairing, so none depends on hue alone
+    pub fn accessible_stroke(self) -> Stroke {
+        let (light, mid, dark) = (Color32::from_gray(230), Color32::from_gray(128), Color32::from_gray(20));
+        match self {
+            BtnColor::Num   => Stroke::new(1.0, light),
+            BtnColor::Op    => Stroke::new(2.5, dark),
+            BtnColor::Fn    => Stroke::new(2.0, light),
+            BtnColor::Ctrl  => Stroke::new(1.0, dark),
+            BtnColor::Eq    => Stroke::new(3.0, light),
+            BtnColor::Del   => Stroke::new(3.0, dark),
+            BtnColor::Ac    => Stroke::new(3.0, mid),
+            BtnColor::Shift => Stroke::new(1.5, mid),
+            BtnColor::Alpha => Stroke::new(2.0, mid),
+        }
+    }
+
+    /// Small corner mark in the accessible palette (keys with word labels need none)
+    pub fn role_mark(self) -> Option<&'static str> {
+        match self {
+            BtnColor::Op   => Some("•"),
+            BtnColor::Fn   => Some("ƒ"),
+            BtnColor::Ctrl => Some("▪"),
+            _              => None,
+        }
+    }
+}
+
 impl BtnDef {
     fn new(label: &'static str, color: BtnColor) -> Self {
         Self { label, shift_label: None, alpha_label: None, color }
@@ -144,42 +206,47 @@ pub fn button_grid(model: ModelType) -> Vec<Vec<BtnDef>> {
     match model {
         ModelType::Fx82MS | ModelType::Fx991ES => {
             vec![
-                // Row 0: SHIFT ALPHA ▲▼◀▶ MODE CLR ON
+                // Row 0: SHIFT ALPHA CALC MODE ON
                 vec![
                     BtnDef::new("SHIFT", Shift),
                     BtnDef::new("ALPHA", Alpha),
-                    BtnDef::new("MODE", Ctrl),
+                    BtnDef::new("CALC", Ctrl).with_alpha("FORMULA"),
+                    BtnDef::new("MODE", Ctrl).with_shift("CLR").with_alpha("MACRO"),
                     BtnDef::new("ON", Ctrl),
                 ],
-                // Row 1: x⁻¹ nCr Pol x³
+                // Row 1: x⁻¹ nCr Pol ∛x
                 vec![
                     BtnDef::new("x⁻¹", Fn).with_shift("x!"),
                     BtnDef::new("nCr", Fn).with_shift("nPr"),
                     BtnDef::new("Pol(", Fn).with_shift("Rec("),
-                    BtnDef::new("∛x", Fn),
+                    BtnDef::new("∛x", Fn).with_shift("x³"),
                 ],
                 // Row 2: a b/c  √  x²  ^  log  ln
                 vec![
                     BtnDef::new("a b/c", Fn).with_shift("d/c"),
                     BtnDef::new("√", Fn).with_shift("x√"),
-                    BtnDef::new("x²", Fn).with_shift("10^x"),
+                    BtnDef::new("x²", Fn).with_shift("√"),
                     BtnDef::new("^", Op),
-                    BtnDef::new("log", Fn).with_shift("e^x"),
-                    BtnDef::new("ln", Fn),
+                    BtnDef::new("log", Fn).with_shift("10^x"),
+                    BtnDef::new("ln", Fn).with_shift("e^x"),
                 ],
                 // Row 3: (-) °'" hyp sin cos tan
                 vec![
-                    BtnDef::new("(-)", Fn),
-                    BtnDef::new("°'\"", Fn),
-                    BtnDef::new("hyp", Fn),
+                    BtnDef::new("(-)", Fn).with_shift("F↔D"),
+                    BtnDef::new("°'\"", Fn).with_shift("◄DMS"),
+                    if model == ModelType::Fx991ES {
+                        BtnDef::new("hyp", Fn).with_shift("log₂")
+                    } else {
+                        BtnDef::new("hyp", Fn)
+                    },
                     BtnDef::new("sin", Fn).with_shift("sin⁻¹"),
                     BtnDef::new("cos", Fn).with_shift("cos⁻¹"),
                     BtnDef::new("tan", Fn).with_shift("tan⁻¹"),
                 ],
                 // Row 4: RCL ENG ( ) , M+
                 vec![
-                    BtnDef::new("RCL", Ctrl).with_shift("STO"),
-                    BtnDef::new("ENG", Ctrl),
+                    BtnDef::new("RCL", Ctrl).with_shift("MC").with_alpha("STO"),
+                    BtnDef::new("ENG", Ctrl).with_shift("r∠θ").with_alpha("SCI"),
                     BtnDef::new("(", Op),
                     BtnDef::new(")", Op),
                     BtnDef::new(",", Op),
@@ -213,9 +280,9 @@ pub fn button_grid(model: ModelType) -> Vec<Vec<BtnDef>> {
                 vec![
                     BtnDef::new("0", Num),
                     BtnDef::new(".", Num),
-                    BtnDef::new("×10^x", Fn),
-                    BtnDef::new("Ans", Fn),
-                    BtnDef::new("=", Eq),
+                    BtnDef::new("×10^x", Fn).with_shift("π"),
+                    BtnDef::new("Ans", Fn).with_shift("e"),
+                    BtnDef::new("=", Eq).with_shift("FULL"),
                 ],
             ]
         }
@@ -227,13 +294,14 @@ pub fn button_grid(model: ModelType) -> Vec<Vec<BtnDef>> {
                     BtnDef::new("SHIFT", Shift),
                     BtnDef::new("ALPHA", Alpha),
                     BtnDef::new("x,θ,T", Fn),
-                    BtnDef::new("MENU", Ctrl),
+                    BtnDef::new("hyp", Fn),
+                    BtnDef::new("MENU", Ctrl).with_alpha("MACRO"),
                     BtnDef::new("ON", Ctrl),
                 ],
                 vec![
-                    BtnDef::new("F1", Ctrl),
-                    BtnDef::new("F2", Ctrl),
-                    BtnDef::new("F3", Ctrl),
+                    BtnDef::new("F1", Ctrl).with_shift("MU"),
+                    BtnDef::new("F2", Ctrl).with_shift("GP"),
+                    BtnDef::new("CALC", Ctrl).with_alpha("FORMULA"),
                     BtnDef::new("F4", Ctrl),
                     BtnDef::new("F5", Ctrl),
                     BtnDef::new("F6", Ctrl),
@@ -280,9 +348,38 @@ pub fn button_grid(model: ModelType) -> Vec<Vec<BtnDef>> {
                     BtnDef::new(".", Num),
                     BtnDef::new("×10^x", Fn),
                     BtnDef::new("Ans", Fn),
-                    BtnDef::new("EXE", Eq),
+                    BtnDef::new("EXE", Eq).with_shift("FULL"),
                 ],
             ]
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLES: [BtnColor; 9] = [
+        BtnColor::Num, BtnColor::Op, BtnColor::Fn, BtnColor::Ctrl, BtnColor::Eq,
+        BtnColor::Del, BtnColor::Ac, BtnColor::Shift, BtnColor::Alpha,
+    ];
+
+    #[test]
+    fn accessible_strokes_tell_every_role_apart() {
+        for (i, a) in ROLES.iter().enumerate() {
+            for b in &ROLES[i + 1..] {
+                assert_ne!(a.accessible_stroke(), b.accessible_stroke(), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn accessible_palette_keeps_the_model_colours() {
+        for model in [ModelType::Fx82MS, ModelType::Fx991ES, ModelType::FxCG50] {
+            let plain = Palette::for_model(model);
+            let accessible = Palette::accessible(model);
+            assert!(!plain.accessible && accessible.accessible);
+            assert_eq!((accessible.body, accessible.btn_num, accessible.btn_op), (plain.body, plain.btn_num, plain.btn_op));
+        }
+    }
+}
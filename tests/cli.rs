@@ -0,0 +1,64 @@
+// Headless --eval mode: argument parsing, the formatted result and the binary's exit codes
+
+use casio_calc::cli::{parse_args, run, EvalArgs};
+use casio_calc::{AngleMode, DisplayFormat};
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+fn eval_args(list: &[&str]) -> EvalArgs {
+    parse_args(&args(list)).unwrap().expect("--eval given")
+}
+
+#[test]
+fn no_eval_starts_the_gui() {
+    assert_eq!(parse_args(&args(&[])), Ok(None));
+    assert_eq!(parse_args(&args(&["--angle", "rad"])), Ok(None));
+}
+
+#[test]
+fn flags_are_parsed() {
+    let parsed = eval_args(&["--eval", "sin(π÷2)", "--angle", "rad", "--format", "fix=3"]);
+    assert_eq!(parsed, EvalArgs {
+        expr:   "sin(π÷2)".to_string(),
+        angle:  AngleMode::Radians,
+        format: DisplayFormat::Fix(3),
+    });
+    assert_eq!(eval_args(&["--format", "sci=4", "--eval", "1"]).format, DisplayFormat::Scientific(4));
+    assert_eq!(eval_args(&["--format", "sci", "--eval", "1"]).format, DisplayFormat::Scientific(9));
+}
+
+#[test]
+fn bad_flags_are_errors() {
+    assert!(parse_args(&args(&["--eval"])).is_err());
+    assert!(parse_args(&args(&["--angle", "turns", "--eval", "1"])).is_err());
+    assert!(parse_args(&args(&["--format", "fix=12", "--eval", "1"])).is_err());
+    // Sci takes 2..9 digits, as on the calculator
+    for sci in ["sci=1", "sci=10"] {
+        assert_eq!(parse_args(&args(&["--format", sci, "--eval", "1"])), Err(format!("Unknown format: {} (sci=2..9)", sci)));
+    }
+    assert!(parse_args(&args(&["--verbose"])).is_err());
+}
+
+#[test]
+fn run_formats_like_the_display() {
+    assert_eq!(run(&eval_args(&["--eval", "2+3×4"])), Ok("14".to_string()));
+    assert_eq!(run(&eval_args(&["--eval", "sin(π÷2)", "--angle", "rad", "--format", "fix=3"])), Ok("1.000".to_string()));
+    assert_eq!(run(&eval_args(&["--eval", "1÷0"])), Err("Math ERROR (div/0)".to_string()));
+}
+
+#[test]
+fn binary_prints_and_exits() {
+    let eval = |list: &[&str]| {
+        let out = std::process::Command::new(env!("CARGO_BIN_EXE_casio-calc"))
+            .args(list)
+            .output()
+            .expect("binary runs");
+        (out.status.code(), String::from_utf8(out.stdout).unwrap(), String::from_utf8(out.stderr).unwrap())
+    };
+    assert_eq!(eval(&["--eval", "2+3×4"]), (Some(0), "14\n".to_string(), String::new()));
+    assert_eq!(eval(&["--eval", "1÷0"]), (Some(1), String::new(), "Math ERROR (div/0)\n".to_string()));
+    assert_eq!(eval(&["--eval", "1", "--angle", "turns"]),
+        (Some(2), String::new(), "Unknown angle mode: turns (deg|rad|gra)\n".to_string()));
+}
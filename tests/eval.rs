@@ -0,0 +1,35 @@
+// Headless API: eval / eval_formatted on a fresh engine
+
+use casio_calc::{eval, eval_formatted};
+
+#[test]
+fn evaluates_a_battery() {
+    let cases = [
+        ("2+3×4", 14.0),
+        ("(2+3)×4", 20.0),
+        ("2^10", 1024.0),
+        ("sqrt(16)", 4.0),
+        ("10÷4", 2.5),
+        ("-3+5", 2.0),
+        ("5!", 120.0),
+        ("nCr(5,2)", 10.0),
+        ("sin(30)", 0.5),
+        ("log(1000)", 3.0),
+    ];
+    for (expr, want) in cases {
+        let got = eval(expr).unwrap_or_else(|e| panic!("{}: {}", expr, e));
+        assert!((got - want).abs() < 1e-9, "{} = {}, want {}", expr, got, want);
+    }
+}
+
+#[test]
+fn formats_like_the_display() {
+    assert_eq!(eval_formatted("1÷3").unwrap(), "0.3333333333");
+    assert_eq!(eval_formatted("2+3*4").unwrap(), "14");
+}
+
+#[test]
+fn reports_errors() {
+    assert_eq!(eval("1÷0").unwrap_err(), "Math ERROR (div/0)");
+    assert!(eval("2+)").is_err());
+}
@@ -2,13 +2,78 @@
 //  ui.rs — egui rendering for 3 CASIO models
 // ============================================================
 
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use egui::{
-    Color32, FontId, Pos2, Rect, Response, RichText, Rounding, Sense,
+    Color32, FontId, Pos2, Rect, RichText, Rounding, Sense,
     Stroke, Ui, Vec2, Frame, Align2,
 };
-use crate::engine::{CalcEngine, AngleMode};
+use crate::engine::{starts_with_at, CalcEngine, AngleMode, DiagnosticKind, DisplayFormat, EMPTY_INPUT, SCI_DIGITS, VARIABLES, hex_color, rgb_color};
+use crate::macros::{load_macros, macros_path, next_macro_name, save_macros_to, Macro, FORMULAS};
 use crate::models::{button_grid, BtnColor, BtnDef, ModelType, Palette};
 
+// ─────────────────────────── CONFIG ────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+/// Initial state the calculator is launched in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AppConfig {
+    pub model:  ModelType,
+    pub angle:  AngleMode,
+    pub format: DisplayFormat,
+    pub theme:  Theme,
+    /// Show the DEG? nudge for `sin(3.14)`-style inputs in degree mode
+    pub radian_hint: bool,
+    /// `3,14` decimals and `;` argument separators
+    pub comma_decimal: bool,
+    /// DEL on an empty input brings back the last calculated expression
+    pub restore_on_del: bool,
+    /// Colour-blind friendly keys: role outlines and marks besides hue
+    pub accessible: bool,
+    /// `Ans` carries the value as displayed, not its full precision
+    pub rounded_ans: bool,
+    /// Base-N (integer) mode: the bitwise operators `and`, `or`, `<<` … work
+    pub base_n: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            model:  ModelType::Fx82MS,
+            angle:  AngleMode::Degrees,
+            format: DisplayFormat::Normal,
+            theme:  Theme::Dark,
+            radian_hint: false,
+            comma_decimal: false,
+            restore_on_del: false,
+            accessible: false,
+            rounded_ans: false,
+            base_n: false,
+        }
+    }
+}
+
+/// Angle unit and display format as last used on one model; switching
+/// back to a model brings them back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrefs {
+    pub angle:  AngleMode,
+    pub format: DisplayFormat,
+}
+
+impl ModelPrefs {
+    /// A model's own defaults, before the user has changed anything on it
+    pub fn defaults(model: ModelType) -> Self {
+        Self { angle: model.default_angle(), format: DisplayFormat::Normal }
+    }
+}
+
 // ─────────────────────────── APP STATE ─────────────────────
 
 pub struct CasioApp {
@@ -20,23 +85,92 @@ pub struct CasioApp {
     shift_mode:  bool,
     alpha_mode:  bool,
     hyp_mode:    bool,
-    show_history:bool,
+    clr_menu:    bool,
+    /// ALPHA+ENG (SCI) is waiting for the significant digit count
+    sci_menu:    bool,
+    sci_auto:    bool,
+    clipboard:   Option<String>,
+    /// Cursor position, counted in chars back from the end of the input
+    scroll:      usize,
+    /// Typing replaces the chars under the cursor instead of pushing them right
+    overwrite:   bool,
+    preview:     Option<(String, String)>,
+    /// The input as last seen by the preview, and when (egui time) it changed
+    edited:      (String, f64),
+    /// Value on the main line while it is an untouched result
+    result:      Option<f64>,
+    /// Open CG50 x√y entry template, drawn after the input
+    template:    Option<RootTemplate>,
+    macros:      Vec<Macro>,
+    /// Where macros defined from the menu are saved
+    macros_file: Option<PathBuf>,
+    macro_menu:  bool,
+    /// ALPHA+CALC (FORMULA) is waiting for a built-in formula's number
+    formula_menu:bool,
+    /// Macro being filled in: its index and the values entered so far
+    macro_run:   Option<(usize, Vec<String>)>,
+    /// Formula CALC last ran, asked for again by CALC on its result
+    calc_formula:Option<String>,
+    calc_prompt: Option<CalcPrompt>,
+    show_vars:   bool,
+    /// Developer overlay with the token stream of the input (Ctrl+Shift+D)
+    show_debug:  bool,
+    /// CG50: whole results up to 0xFFFFFF also show as a colour swatch (Alt+H)
+    color_mode:  bool,
+    restore_on_del: bool,
+    /// What the app was launched with; ON starts a new engine from it
+    config:      AppConfig,
+    /// Settings of the models switched away from, keyed by model
+    model_prefs: HashMap<ModelType, ModelPrefs>,
     palette:     Palette,
+    theme:       Theme,
 }
 
 impl CasioApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>, model: ModelType) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, model: ModelType) -> Self {
+        Self::with_config(cc, AppConfig { model, angle: model.default_angle(), ..AppConfig::default() })
+    }
+
+    pub fn with_config(_cc: &eframe::CreationContext<'_>, config: AppConfig) -> Self {
+        Self::from_config(config)
+    }
+
+    /// `with_config` without a window, for tests and embedders
+    pub fn from_config(config: AppConfig) -> Self {
         Self {
-            engine:      CalcEngine::new(),
-            palette:     Palette::for_model(model),
-            model,
+            engine:      configured_engine(&config),
+            config,
+            palette:     palette_for(config.model, config.accessible),
+            model:       config.model,
             input:       "0".to_string(),
             top_line:    String::new(),
             error:       false,
             shift_mode:  false,
             alpha_mode:  false,
             hyp_mode:    false,
-            show_history:false,
+            clr_menu:    false,
+            sci_menu:    false,
+            sci_auto:    false,
+            result:      None,
+            template:    None,
+            macros:      load_macros(),
+            macros_file: macros_path(),
+            macro_menu:  false,
+            formula_menu:false,
+            macro_run:   None,
+            calc_formula:None,
+            calc_prompt: None,
+            clipboard:   None,
+            scroll:      0,
+            overwrite:   false,
+            preview:     None,
+            edited:      (String::new(), 0.0),
+            show_vars:   false,
+            show_debug:  false,
+            color_mode:  false,
+            restore_on_del: config.restore_on_del,
+            model_prefs: HashMap::new(),
+            theme:       config.theme,
         }
     }
 }
@@ -44,16 +178,41 @@ impl CasioApp {
 impl eframe::App for CasioApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.handle_keyboard(ctx);
-        // Dark background
-        ctx.set_visuals(egui::Visuals::dark());
+        if let Some(text) = self.clipboard.take() {
+            ctx.output_mut(|o| o.copied_text = text);
+        }
+        let background = match self.theme {
+            Theme::Dark => {
+                ctx.set_visuals(egui::Visuals::dark());
+                Color32::from_rgb(8, 8, 18)
+            }
+            Theme::Light => {
+                ctx.set_visuals(egui::Visuals::light());
+                Color32::from_rgb(225, 225, 232)
+            }
+        };
 
         egui::CentralPanel::default()
-            .frame(Frame::none().fill(Color32::from_rgb(8, 8, 18)))
+            .frame(Frame::none().fill(background))
             .show(ctx, |ui| {
                 ui.vertical_centered(|ui| {
                     self.draw_calculator(ui);
                 });
             });
+
+        if self.show_vars {
+            egui::Window::new("Variables")
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| self.draw_variables(ui));
+        }
+
+        if self.show_debug {
+            egui::Window::new("Tokens")
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| self.draw_debug(ui));
+        }
     }
 }
 
@@ -64,7 +223,7 @@ impl CasioApp {
         let dark_color = p.body_dark;
 
         // Outer shell
-        let (rect, _) = ui.allocate_exact_size(Vec2::new(340.0, 720.0), Sense::hover());
+        let (rect, _) = ui.allocate_exact_size(shell_size(self.model), Sense::hover());
         let painter = ui.painter();
 
         // Shadow
@@ -91,6 +250,12 @@ impl CasioApp {
 
 
     fn handle_keyboard(&mut self, ctx: &egui::Context) {
+        let text_focused = ctx.wants_keyboard_input();
+        let popup_open   = ctx.memory(|m| m.any_popup_open());
+        if !should_handle_global_keys(text_focused, popup_open) {
+            return;
+        }
+
         ctx.input(|i| {
             for event in &i.events {
                 match event {
@@ -101,17 +266,35 @@ impl CasioApp {
                                 self.handle_button(text);
                             }
                             "+" | "-" => self.handle_button(text),
+                            "!" | "%" => self.handle_button(text),
+                            // Macro placeholders
+                            "?" => self.handle_button(text),
+                            "A" | "B" | "C" | "D" | "E" | "F" | "X" | "Y" | "M" => self.handle_button(text),
                   "*" => self.handle_button("×"), // המרה לסמל של המחשבון
                   "/" => self.handle_button("÷"), // המרה לסמל של המחשבון
                   _ => {}
                         }
                     }
+                    egui::Event::Paste(text) => self.paste(text),
                     // קליטת מקשים מיוחדים (Enter, Backspace, Escape)
-                    egui::Event::Key { key, pressed: true, .. } => {
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => {
+                        if modifiers.ctrl && modifiers.shift && *key == egui::Key::D {
+                            self.show_debug = !self.show_debug;
+                            continue;
+                        }
+                        // Alt+letter → function key (see FUNCTION_KEYS)
+                        if modifiers.alt {
+                            if let Some(label) = function_for_key(*key) {
+                                self.handle_button(label);
+                            }
+                            continue;
+                        }
                         match key {
                             egui::Key::Enter => self.handle_button("="),
                   egui::Key::Backspace => self.handle_button("DEL"),
                   egui::Key::Escape => self.handle_button("AC"),
+                  egui::Key::ArrowLeft => self.arrow_left(),
+                  egui::Key::ArrowRight => self.arrow_right(),
                   _ => {}
                         }
                     }
@@ -181,6 +364,11 @@ impl CasioApp {
     }
 
     fn draw_display(&mut self, ui: &mut Ui) {
+        let now = ui.input(|i| i.time);
+        let preview = self.live_preview(now);
+        if let Some(wait) = self.preview_wait(now) {
+            ui.ctx().request_repaint_after(std::time::Duration::from_secs_f64(wait));
+        }
         let p = &self.palette;
         Frame::none()
             .fill(p.display_bg)
@@ -188,7 +376,7 @@ impl CasioApp {
             .rounding(Rounding::same(4.0))
             .stroke(Stroke::new(2.0, Color32::from_black_alpha(150)))
             .show(ui, |ui| {
-                ui.set_min_width(310.0);
+                ui.set_min_width(content_width(self.model));
 
                 // Status bar
                 ui.horizontal(|ui| {
@@ -202,6 +390,28 @@ impl CasioApp {
                     if self.hyp_mode {
                         ui.label(RichText::new("HYP").font(FontId::monospace(9.0)).color(Color32::from_rgb(80, 160, 255)));
                     }
+                    if self.result.is_none() && !self.error {
+                        let mode = if self.overwrite { "OVR" } else { "INS" };
+                        ui.label(RichText::new(mode).font(FontId::monospace(9.0)).color(p.display_text.linear_multiply(0.6)));
+                    }
+                    if self.sci_auto || matches!(self.engine.format, DisplayFormat::Scientific(_)) {
+                        ui.label(RichText::new("SCI").font(FontId::monospace(9.0)).color(p.display_text.linear_multiply(0.8)));
+                    }
+                    // Advisory only; click to dismiss
+                    if self.engine.deg_warning {
+                        let hint = ui.add(egui::Label::new(
+                            RichText::new("DEG?").font(FontId::monospace(9.0)).color(Color32::from_rgb(220, 120, 0)),
+                        ).sense(Sense::click()));
+                        if hint.on_hover_text("sin/cos/tan got a radian-looking value in degree mode").clicked() {
+                            self.engine.deg_warning = false;
+                        }
+                    }
+                    if self.color_mode && self.model == ModelType::FxCG50 {
+                        ui.label(RichText::new("RGB").font(FontId::monospace(9.0)).color(p.display_text.linear_multiply(0.8)));
+                    }
+                    if self.engine.memory_in_use() {
+                        ui.label(RichText::new("M").font(FontId::monospace(10.0)).color(p.display_text));
+                    }
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.label(
                             RichText::new("Math")
@@ -230,23 +440,191 @@ impl CasioApp {
 
                 // Main display line
                 ui.add_space(2.0);
-                let font_size = if self.input.len() > 14 { 18.0 } else { 30.0 };
+                let len = self.input.chars().count();
+                let font_size = if len > 14 { 18.0 } else { 30.0 };
                 let color = if self.error { Color32::from_rgb(200, 30, 30) } else { p.display_text };
+                let cursor = len - self.scroll.min(len);
+                let shown = visible_window(&self.input, cursor, DISPLAY_CHARS);
+                // Cursor position within the shown slice (it ends at the window's right edge)
+                let shown_cursor = if len <= DISPLAY_CHARS {
+                    cursor
+                } else {
+                    DISPLAY_CHARS - (cursor.max(DISPLAY_CHARS).min(len) - cursor)
+                };
+                let segments = if self.error { None } else { highlight_segments(&shown, shown_cursor) };
+                let template = self.template.as_ref();
+                // CG50 natural display raises exponents instead of printing `^`
+                let raise = self.model == ModelType::FxCG50;
+                // No caret over a result, an error or an open template
+                let time = ui.input(|i| i.time);
+                let show_caret = self.result.is_none() && template.is_none() && caret_visible(time);
+                if self.result.is_none() && !self.error {
+                    ui.ctx().request_repaint_after(std::time::Duration::from_secs_f64(CARET_BLINK_SECS));
+                }
+                let overwrite = self.overwrite;
+                let swatch = self.result
+                    .filter(|_| self.color_mode && self.model == ModelType::FxCG50)
+                    .and_then(|val| Some((rgb_color(val)?, hex_color(val)?)));
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                    ui.label(
-                        RichText::new(&self.input)
-                            .font(FontId::monospace(font_size))
-                            .color(color)
-                            .strong(),
-                    );
+                    match segments {
+                        Some(mut segments) => {
+                            // An empty input's placeholder 0 isn't part of the template
+                            if template.is_some() && self.input == "0" { segments.clear(); }
+                            let mut job = egui::text::LayoutJob::default();
+                            let mut raised = false;
+                            // Chars of `shown` walked so far, and how many of them made it into the job
+                            let (mut seen, mut laid) = (0, 0);
+                            let mut caret_at = None;
+                            for (text, kind) in segments {
+                                let mut text = text.as_str();
+                                let typed = text.chars().count();
+                                let superscript = std::mem::take(&mut raised);
+                                if raise && kind == Segment::Operator && text.ends_with('^') {
+                                    text = text.trim_end_matches('^');
+                                    raised = true;
+                                }
+                                let drawn = text.chars().count();
+                                if caret_at.is_none() && shown_cursor <= seen + typed {
+                                    caret_at = Some(laid + (shown_cursor - seen).min(drawn));
+                                }
+                                seen += typed;
+                                laid += drawn;
+                                job.append(text, 0.0, egui::TextFormat {
+                                    font_id:    FontId::monospace(if superscript { font_size * 0.6 } else { font_size }),
+                                    color:      segment_color(kind, color),
+                                    background: if kind == Segment::Matched {
+                                        color.linear_multiply(0.2)
+                                    } else {
+                                        Color32::TRANSPARENT
+                                    },
+                                    valign:     if superscript { egui::Align::TOP } else { egui::Align::BOTTOM },
+                                    ..Default::default()
+                                });
+                            }
+                            if let Some(t) = template {
+                                append_template(&mut job, t, font_size, color);
+                            }
+                            let galley = ui.fonts(|f| f.layout_job(job));
+                            let caret = galley.pos_from_ccursor(egui::text::CCursor::new(caret_at.unwrap_or(laid)));
+                            let rect = ui.label(galley).rect;
+                            if show_caret {
+                                let caret = caret.translate(rect.min.to_vec2());
+                                // A bar between chars to insert, an underline under the char to overwrite
+                                let (from, to) = if overwrite {
+                                    let under = caret.left_bottom();
+                                    (under, under + Vec2::new(font_size * 0.6, 0.0))
+                                } else {
+                                    (caret.left_top(), caret.left_bottom())
+                                };
+                                ui.painter().line_segment([from, to], Stroke::new(2.0, color));
+                            }
+                        }
+                        None => {
+                            let flat = template.map(RootTemplate::to_flat).unwrap_or_default();
+                            ui.label(
+                                RichText::new(format!("{}{}", shown, flat))
+                                    .font(FontId::monospace(font_size))
+                                    .color(color)
+                                    .strong(),
+                            );
+                        }
+                    }
+                    // Left of the number: the colour it codes, then its #RRGGBB
+                    if let Some(((r, g, b), hex)) = swatch {
+                        ui.add_space(6.0);
+                        let (rect, _) = ui.allocate_exact_size(Vec2::splat(font_size * 0.7), Sense::hover());
+                        ui.painter().rect(rect, Rounding::same(3.0), Color32::from_rgb(r, g, b), Stroke::new(1.0, color));
+                        ui.label(RichText::new(hex).font(FontId::monospace(11.0)).color(color.linear_multiply(0.7)));
+                    }
                 });
 
+                // Live result preview while typing
+                if let Some(text) = preview {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                        ui.label(
+                            RichText::new(text)
+                                .font(FontId::monospace(11.0))
+                                .color(p.display_text.linear_multiply(0.45)),
+                        );
+                    });
+                }
+
                 ui.add_space(2.0);
             });
 
         ui.add_space(6.0);
     }
 
+    /// Memory inspector: one row per variable; clicking inserts it into the input
+    fn draw_variables(&mut self, ui: &mut Ui) {
+        egui::Grid::new("vars").num_columns(2).striped(true).show(ui, |ui| {
+            for (name, val) in self.engine.variables() {
+                let value = self.engine.format_result(val);
+                if ui.button(RichText::new(&name).monospace()).clicked() {
+                    // The accumulator has no token of its own; insert its value
+                    let insert = if name == "M+" { value.clone() } else { name.clone() };
+                    self.append(&insert);
+                }
+                ui.label(RichText::new(value).monospace());
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Developer overlay: the input as the engine tokenizes it, one token per
+    /// row, then the parse tree
+    fn draw_debug(&self, ui: &mut Ui) {
+        let expr = &self.input;
+        ui.label(RichText::new(expr).monospace().strong());
+        match self.engine.debug_tokens(expr) {
+            Ok(tokens) => {
+                for (i, token) in tokens.iter().enumerate() {
+                    ui.label(RichText::new(format!("{:>3}  {:?}", i, token)).monospace());
+                }
+            }
+            Err(e) => { ui.label(RichText::new(e).monospace().color(Color32::from_rgb(200, 30, 30))); }
+        }
+        ui.separator();
+        match self.engine.parse(expr) {
+            Ok(ast) => { ui.label(RichText::new(format!("{:#?}", ast)).monospace()); }
+            Err(e) => { ui.label(RichText::new(e).monospace().color(Color32::from_rgb(200, 30, 30))); }
+        }
+    }
+
+    /// Formatted result of the current input, re-evaluated once the input
+    /// has stopped changing for `PREVIEW_DEBOUNCE_SECS`. On a syntax or
+    /// argument error, the suggested fix instead; None while typing, for
+    /// other errors, or when it would just echo the input.
+    fn live_preview(&mut self, now: f64) -> Option<String> {
+        if self.error { return None; }
+        if self.edited.0 != self.input {
+            self.edited = (self.input.clone(), now);
+        }
+        if self.preview_wait(now).is_some() { return None; }
+        if self.preview.as_ref().map(|(src, _)| src != &self.input).unwrap_or(true) {
+            let expr = self.input.clone();
+            let text = match self.engine.try_evaluate(&expr) {
+                Ok(val) => self.engine.format_result(val),
+                Err(_) => match self.engine.validate(&expr) {
+                    Err(d) if matches!(d.kind, DiagnosticKind::Syntax | DiagnosticKind::Argument) => {
+                        d.suggestion.unwrap_or(d.message)
+                    }
+                    _ => String::new(),
+                },
+            };
+            self.preview = Some((self.input.clone(), text));
+        }
+        let (_, text) = self.preview.as_ref()?;
+        if text.is_empty() || *text == self.input { None } else { Some(text.clone()) }
+    }
+
+    /// Seconds until the preview for the latest edit is due, if it is still waiting
+    fn preview_wait(&self, now: f64) -> Option<f64> {
+        let stale = self.preview.as_ref().map(|(src, _)| src != &self.input).unwrap_or(true);
+        let wait = self.edited.1 + PREVIEW_DEBOUNCE_SECS - now;
+        (stale && self.edited.0 == self.input && wait > 0.0).then_some(wait)
+    }
+
     fn draw_model_switcher(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             ui.add_space(4.0);
@@ -271,9 +649,9 @@ impl CasioApp {
                     FontId::proportional(9.5),
                     color,
                 );
-                if resp.clicked() {
-                    self.model = m;
-                    self.palette = Palette::for_model(m);
+                if resp.clicked() && self.model != m {
+                    ui.ctx().send_viewport_cmd(egui::ViewportCommand::InnerSize(window_size(m)));
+                    self.switch_model(m);
                 }
                 ui.add_space(2.0);
             }
@@ -281,12 +659,28 @@ impl CasioApp {
         ui.add_space(6.0);
     }
 
+    /// Keep the current model's angle and format, then bring up `model` with
+    /// its own: as the user left them, or its defaults on first use
+    fn switch_model(&mut self, model: ModelType) {
+        self.model_prefs.insert(self.model, ModelPrefs { angle: self.engine.angle, format: self.engine.format });
+        let prefs = self.model_prefs.get(&model).copied().unwrap_or_else(|| ModelPrefs::defaults(model));
+        self.engine.angle = prefs.angle;
+        self.engine.format = prefs.format;
+        self.model = model;
+        self.palette = palette_for(model, self.palette.accessible);
+        self.engine.set_display_digits(model.digits());
+        if let Some(val) = self.result {
+            self.show_result(val);
+        }
+    }
+
     fn draw_buttons(&mut self, ui: &mut Ui) {
         let rows = button_grid(self.model);
+        let width = content_width(self.model);
         for row in &rows {
             ui.horizontal(|ui| {
                 ui.add_space(2.0);
-                let btn_w = (310.0 - (row.len() as f32 - 1.0) * 4.0) / row.len() as f32;
+                let btn_w = (width - (row.len() as f32 - 1.0) * KEY_GAP) / row.len() as f32;
                 for btn in row {
                     if self.draw_button(ui, btn, btn_w) {
                         self.handle_button(btn.label);
@@ -300,7 +694,7 @@ impl CasioApp {
 
     fn draw_button(&self, ui: &mut Ui, btn: &BtnDef, width: f32) -> bool {
         let p = &self.palette;
-        let height = 36.0;
+        let height = KEY_H;
 
         let base_color = match btn.color {
             BtnColor::Num   => p.btn_num,
@@ -346,7 +740,17 @@ impl CasioApp {
             Rounding { nw: 5.0, ne: 5.0, sw: 0.0, se: 0.0 },
             Color32::from_white_alpha(25),
         );
-        painter.rect_stroke(rect, Rounding::same(5.0), Stroke::new(1.0, darken(base_color, 0.6)));
+        let outline = if p.accessible { btn.color.accessible_stroke() } else { Stroke::new(1.0, darken(base_color, 0.6)) };
+        painter.rect_stroke(rect, Rounding::same(5.0), outline);
+        if let Some(mark) = btn.color.role_mark().filter(|_| p.accessible) {
+            painter.text(
+                rect.left_bottom() + Vec2::new(3.0, -1.0),
+                Align2::LEFT_BOTTOM,
+                mark,
+                FontId::proportional(8.0),
+                outline.color,
+            );
+        }
 
         // Shift label (small, top)
         if let Some(sl) = btn.shift_label {
@@ -384,11 +788,43 @@ impl CasioApp {
     }
 
     fn handle_button(&mut self, label: &str) {
-        self.error = false;
+        if self.error && !self.clear_error(label) {
+            return;
+        }
+        self.sci_auto = false;
+        self.preview = None;
+
+        if self.clr_menu {
+            self.handle_clr_menu(label);
+            return;
+        }
+        if self.macro_menu {
+            self.handle_macro_menu(label);
+            return;
+        }
+        if self.formula_menu {
+            self.handle_formula_menu(label);
+            return;
+        }
+        if self.sci_menu {
+            self.handle_sci_menu(label);
+            return;
+        }
+        if self.template.is_some() && self.template_key(label) {
+            return;
+        }
+        if self.macro_run.is_some() && self.macro_key(label) {
+            return;
+        }
+        if self.calc_prompt.is_some() && self.calc_key(label) {
+            return;
+        }
 
         match label {
             "AC" => {
                 self.input = "0".to_string();
+                self.scroll = 0;
+                self.result = None;
                 self.top_line.clear();
                 self.shift_mode = false;
                 self.alpha_mode = false;
@@ -396,35 +832,74 @@ impl CasioApp {
                 self.error = false;
             }
 
+            // SHIFT+DEL (INS) flips insert ↔ overwrite
+            "DEL" if self.shift_mode => {
+                self.overwrite = !self.overwrite;
+                self.shift_mode = false;
+            }
+
+            // With restore_on_del, DEL on an empty input recalls the last expression
+            "DEL" if self.restore_on_del && self.input == "0" && !self.engine.history.is_empty() => {
+                if let Some((expr, _)) = self.engine.history.back() {
+                    self.input = expr.clone();
+                    self.scroll = 0;
+                    self.result = None;
+                }
+            }
+
+            // Insert mode deletes left of the cursor, overwrite the char under it
             "DEL" => {
-                if self.input.len() > 1 {
-                    self.input.pop();
+                self.result = None;
+                let mut tail = self.input.split_off(self.cursor_byte());
+                if self.overwrite && !tail.is_empty() {
+                    tail.remove(0);
                 } else {
+                    self.input.pop();
+                }
+                self.input.push_str(&tail);
+                self.scroll = tail.chars().count();
+                if self.input.is_empty() {
                     self.input = "0".to_string();
+                    self.scroll = 0;
                 }
             }
 
-            "=" | "EXE" => {
-                let expr = self.input
-                    .replace("×", "*")
-                    .replace("÷", "/")
-                    .replace("−", "-")
-                    .replace("×10^x", "*10^");
-
-                match self.engine.evaluate(&expr) {
-                    Ok(val) => {
-                        self.top_line = format!("{}=", self.input);
-                        self.input = self.engine.format_result(val);
-                    }
-                    Err(e) => {
-                        self.top_line = self.input.clone();
-                        self.input = e;
-                        self.error = true;
-                    }
+            // SHIFT+= flips the result between the display rounding and every
+            // digit of the f64 behind it
+            "=" | "EXE" if self.shift_mode => {
+                if self.result.is_none() {
+                    self.calc_formula = None;
+                    self.evaluate_input();
+                }
+                if let Some(val) = self.result {
+                    let full = self.engine.format_full(val);
+                    if self.input == full { self.show_result(val); } else { self.input = full; }
                 }
                 self.shift_mode = false;
+            }
+
+            "=" | "EXE" => {
+                self.calc_formula = None;
+                self.evaluate_input();
+            }
+
+            // ALPHA+CALC lists the built-in formulas
+            "CALC" if self.alpha_mode => {
+                self.formula_menu = true;
+                self.top_line = FORMULAS.iter().enumerate()
+                    .map(|(i, f)| format!("{}:{}", i + 1, f.name))
+                    .collect::<Vec<_>>()
+                    .join(" ");
                 self.alpha_mode = false;
-                self.hyp_mode = false;
+            }
+
+            // CALC asks for each variable the formula reads, then evaluates it.
+            // On a CALC result it asks again for the same formula.
+            "CALC" => {
+                if self.result.is_none() || self.calc_formula.is_none() {
+                    self.calc_formula = Some(self.input.clone());
+                }
+                self.start_calc();
             }
 
             "SHIFT" => {
@@ -437,46 +912,69 @@ impl CasioApp {
                 self.shift_mode = false;
             }
 
+            // ALPHA+MODE (CG50: ALPHA+MENU) lists the saved macros
+            "MODE" | "MENU" if self.alpha_mode => {
+                self.macro_menu = true;
+                self.top_line = self.macros.iter().enumerate().take(MAX_MACROS)
+                    .map(|(i, m)| format!("{}:{}", i + 1, m.name))
+                    .chain(["=:SAVE".to_string()])
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.alpha_mode = false;
+            }
+
             "MODE" => {
-                self.engine.cycle_angle();
+                if self.shift_mode {
+                    self.clr_menu = true;
+                    self.top_line = "CLR 1:Setup 2:Mem 3:All".to_string();
+                    self.shift_mode = false;
+                } else {
+                    self.engine.cycle_angle();
+                }
             }
 
             "ON" => {
                 self.input = "0".to_string();
+                self.scroll = 0;
                 self.top_line.clear();
                 self.shift_mode = false;
                 self.alpha_mode = false;
                 self.hyp_mode = false;
                 self.error = false;
-                self.engine = CalcEngine::new();
+                // A new engine, but on this model's digits and defaults
+                let defaults = ModelPrefs::defaults(self.model);
+                self.engine = configured_engine(&AppConfig {
+                    model:  self.model,
+                    angle:  defaults.angle,
+                    format: defaults.format,
+                    ..self.config
+                });
             }
 
             "hyp" => {
-                self.hyp_mode = !self.hyp_mode;
+                if self.shift_mode && self.model == ModelType::Fx991ES {
+                    // SHIFT+hyp is log₂ on the 991ES only
+                    self.append("log₂(");
+                    self.shift_mode = false;
+                } else {
+                    self.hyp_mode = !self.hyp_mode;
+                }
             }
 
-            "Ans" => self.append("Ans"),
+            "Ans" => {
+                // A second Ans in a row gets an explicit × rather than `AnsAns`
+                let ans = if self.input[..self.cursor_byte()].ends_with("Ans") { "×Ans" } else { "Ans" };
+                self.append(if self.shift_mode { "ℯ" } else { ans });
+                self.shift_mode = false;
+            }
 
-            "×10^x" => self.append("×10^"),
+            "×10^x" | "EXP" => {
+                self.append(if self.shift_mode { "π" } else { "×10^" });
+                self.shift_mode = false;
+            }
 
             "sin" | "cos" | "tan" => {
-                let fn_name = if self.shift_mode {
-                    match label {
-                        "sin" => "asin",
-                        "cos" => "acos",
-                        "tan" => "atan",
-                        _     => label,
-                    }
-                } else if self.hyp_mode {
-                    match label {
-                        "sin" => "sinh",
-                        "cos" => "cosh",
-                        "tan" => "tanh",
-                        _     => label,
-                    }
-                } else {
-                    label
-                };
+                let fn_name = trig_name(label, self.hyp_mode, self.shift_mode);
                 self.append(&format!("{}(", fn_name));
                 self.shift_mode = false;
                 self.hyp_mode = false;
@@ -501,14 +999,31 @@ impl CasioApp {
             }
 
             "√" => {
-                self.append(if self.shift_mode { "x√(" } else { "sqrt(" });
+                // SHIFT: x√ — index already typed, radicand follows (`3√(8)`)
+                self.append(if self.shift_mode { "√(" } else { "sqrt(" });
                 self.shift_mode = false;
             }
 
-            "∛x" => { self.append("cbrt("); }
+            "^" => {
+                // SHIFT+^ is x√ on the CG50; the other grids keep it on √
+                if self.shift_mode && self.model == ModelType::FxCG50 {
+                    self.template = Some(RootTemplate::default());
+                } else {
+                    self.append("^");
+                }
+                self.shift_mode = false;
+            }
+
+            "∛x" => {
+                // SHIFT gives the inverse: x³
+                let cube = if self.model.natural_display() { "³" } else { "^3" };
+                self.append(if self.shift_mode { cube } else { "cbrt(" });
+                self.shift_mode = false;
+            }
 
             "x²" => {
-                self.append(if self.shift_mode { "^(0.5)" } else { "^2" });
+                let square = if self.model.natural_display() { "²" } else { "^2" };
+                self.append(if self.shift_mode { "^(0.5)" } else { square });
                 self.shift_mode = false;
             }
 
@@ -521,6 +1036,11 @@ impl CasioApp {
                 }
             }
 
+            "Pol(" => {
+                self.append(if self.shift_mode { "Rec(" } else { "Pol(" });
+                self.shift_mode = false;
+            }
+
             "nCr" => {
                 if self.shift_mode {
                     self.append("nPr(");
@@ -530,23 +1050,29 @@ impl CasioApp {
                 }
             }
 
+            "(-)" if self.shift_mode => {
+                self.toggle_fraction_decimal();
+                self.shift_mode = false;
+            }
+
             "(-)" => {
+                // Unary minus: ASCII `-`, distinct from the `−` subtraction key
                 if self.input == "0" {
                     self.input = "-".to_string();
                 } else {
-                    self.append("×(-1)");
+                    self.append("-");
                 }
             }
 
             "M+" => {
                 if self.shift_mode {
-                    if let Ok(val) = self.engine.evaluate(&self.input.replace("×","*").replace("÷","/").replace("−","-")) {
+                    if let Ok(val) = self.engine.evaluate(&self.input) {
                         self.engine.m_minus_op(val);
                         self.top_line = format!("M = {}", self.engine.format_result(self.engine.recall_m()));
                     }
                     self.shift_mode = false;
                 } else {
-                    if let Ok(val) = self.engine.evaluate(&self.input.replace("×","*").replace("÷","/").replace("−","-")) {
+                    if let Ok(val) = self.engine.evaluate(&self.input) {
                         self.engine.m_plus_op(val);
                         self.top_line = format!("M = {}", self.engine.format_result(self.engine.recall_m()));
                     }
@@ -554,21 +1080,103 @@ impl CasioApp {
             }
 
             "RCL" => {
-                let m = self.engine.recall_m();
-                self.top_line = format!("M = {}", self.engine.format_result(m));
-                self.append(&self.engine.format_result(m).clone());
+                if self.alpha_mode {
+                    // STO arrow: `expr→A`
+                    self.append("→");
+                    self.alpha_mode = false;
+                } else if self.shift_mode {
+                    self.engine.clear_m();
+                    self.top_line = "M CLEARED".to_string();
+                    self.shift_mode = false;
+                } else {
+                    let m = self.engine.recall_m();
+                    self.top_line = format!("M = {}", self.engine.format_result(m));
+                    self.append(&self.engine.format_result(m).clone());
+                }
+            }
+
+            "ENG" if self.shift_mode => {
+                // SHIFT+ENG: Pol/Rec results as (x,y) ↔ r∠θ
+                self.engine.toggle_coord_form();
+                if let Some((x, y)) = self.engine.coords {
+                    self.top_line = self.engine.format_coords(x, y);
+                }
+                self.shift_mode = false;
+            }
+
+            // ALPHA+ENG: Sci setup, asks for 2~9 significant digits
+            "ENG" if self.alpha_mode => {
+                self.sci_menu = true;
+                self.top_line = "Sci 2~9?".to_string();
+                self.alpha_mode = false;
             }
 
             "ENG" => {
-                if let Ok(val) = self.engine.evaluate(&self.input.replace("×","*").replace("÷","/").replace("−","-")) {
-                    use crate::engine::DisplayFormat;
+                // Reformat only — doesn't count as a new calculation
+                if let Ok(val) = self.engine.try_evaluate(&self.input) {
+                    let format = self.engine.format;
+                    self.scroll = 0;
                     self.engine.format = DisplayFormat::Engineering;
                     self.input = self.engine.format_result(val);
-                    self.engine.format = DisplayFormat::Normal;
+                    self.engine.format = format;
+                }
+            }
+
+            "°'\"" if self.shift_mode => {
+                self.toggle_dms();
+                self.shift_mode = false;
+            }
+
+            // Each press marks the next sexagesimal part: 1°, then 30', then 15"
+            "°'\"" => {
+                let before = self.input[..self.cursor_byte()].trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+                let mark = match before.chars().last() {
+                    Some('°')  => "'",
+                    Some('\'') => "\"",
+                    _          => "°",
+                };
+                self.append(mark);
+            }
+
+            // On a result, a b/c turns fraction display on/off and SHIFT (d/c)
+            // flips mixed ↔ improper; while typing it enters the ⌟ mark
+            "a b/c" => {
+                match self.result {
+                    Some(val) => {
+                        if self.shift_mode {
+                            self.engine.mixed = !self.engine.mixed;
+                            self.engine.fractions = true;
+                        } else {
+                            self.engine.fractions = !self.engine.fractions;
+                        }
+                        self.show_result(val);
+                    }
+                    None => self.append("⌟"),
                 }
+                self.shift_mode = false;
             }
 
-            "°'\"" => { self.append("°"); }
+            "VARS" => self.show_vars = !self.show_vars,
+
+            // Colour results are a CG50 feature; elsewhere the shortcut does nothing
+            "RGB" => if self.model == ModelType::FxCG50 { self.color_mode = !self.color_mode },
+
+            "LaTeX" => match crate::latex::to_latex(&self.input) {
+                Ok(tex) => {
+                    self.clipboard = Some(tex);
+                    self.top_line = "LaTeX copied".to_string();
+                }
+                Err(_) => self.top_line = "LaTeX: Syntax ERROR".to_string(),
+            },
+
+            // CG50 business quick keys: SHIFT+F1 markup, SHIFT+F2 gross profit
+            "F1" | "F2" if self.shift_mode => {
+                self.append(if label == "F1" { "markup(" } else { "margin(" });
+                self.shift_mode = false;
+            }
+
+            // The comma locale keeps `,` for decimals; arguments split on `;`
+            "," if self.engine.comma_decimal => self.append(";"),
 
             _ => {
                 // Regular character append
@@ -577,16 +1185,737 @@ impl CasioApp {
         }
     }
 
+    fn evaluate_input(&mut self) {
+        // Nothing to calculate (only a dangling operator, say): leave the display be
+        if matches!(self.engine.parse(&self.input), Err(e) if e == EMPTY_INPUT) { return; }
+        self.scroll = 0;
+
+        match self.engine.evaluate(&self.input) {
+            Ok(val) => {
+                self.top_line = format!("{}=", self.input);
+                self.show_result(val);
+            }
+            Err(e) => {
+                self.top_line = self.input.clone();
+                self.input = e;
+                self.error = true;
+                self.result = None;
+            }
+        }
+        self.shift_mode = false;
+        self.alpha_mode = false;
+        self.hyp_mode = false;
+    }
+
+    /// SHIFT+MODE (CLR) menu: 1 = setup, 2 = memory, 3 = all; any other key cancels
+    fn handle_clr_menu(&mut self, label: &str) {
+        self.clr_menu = false;
+        let msg = match label {
+            "1" => { self.engine.clear_setup();  "Setup cleared" }
+            "2" => { self.engine.clear_memory(); "Memory cleared" }
+            "3" => { self.engine.clear_all();    "All cleared" }
+            _   => { self.top_line.clear(); return; }
+        };
+        // The setup goes back to this model's defaults (radians on the CG50)
+        if label != "2" {
+            self.engine.angle = self.model.default_angle();
+        }
+        self.top_line = msg.to_string();
+        self.input = "0".to_string();
+        self.scroll = 0;
+    }
+
+    /// Sci menu: 2..9 switches to scientific with that many significant
+    /// digits and reformats the shown result; any other key cancels
+    fn handle_sci_menu(&mut self, label: &str) {
+        self.sci_menu = false;
+        self.top_line.clear();
+        let Some(n) = label.parse::<u8>().ok().filter(|n| SCI_DIGITS.contains(n)) else { return };
+        self.engine.format = DisplayFormat::Scientific(n);
+        if let Some(val) = self.result {
+            self.show_result(val);
+        }
+    }
+
+    /// Macro menu: a digit starts that macro, `=` saves the input as a new
+    /// one; any other key cancels
+    fn handle_macro_menu(&mut self, label: &str) {
+        self.macro_menu = false;
+        if label == "=" || label == "EXE" {
+            self.define_macro();
+            return;
+        }
+        let index = label.parse::<usize>().ok().filter(|&n| n >= 1 && n <= self.macros.len().min(MAX_MACROS));
+        match index {
+            Some(n) => {
+                self.macro_run = Some((n - 1, Vec::new()));
+                self.input = "0".to_string();
+                self.scroll = 0;
+                self.prompt_macro();
+            }
+            None => self.top_line.clear(),
+        }
+    }
+
+    /// Formula menu: a digit enters that formula and CALCs it; any other key cancels
+    fn handle_formula_menu(&mut self, label: &str) {
+        self.formula_menu = false;
+        match label.parse::<usize>().ok().and_then(|n| FORMULAS.get(n.checked_sub(1)?)) {
+            Some(f) => {
+                self.input = f.expr.to_string();
+                self.scroll = 0;
+                self.result = None;
+                self.calc_formula = Some(self.input.clone());
+                self.start_calc();
+            }
+            None => self.top_line.clear(),
+        }
+    }
+
+    /// The input becomes the next `m1`, `m2` … macro and the list is saved
+    fn define_macro(&mut self) {
+        if self.error || self.input == "0" {
+            self.top_line.clear();
+            return;
+        }
+        if self.macros.len() >= MAX_MACROS {
+            self.top_line = "MACROS FULL".to_string();
+            return;
+        }
+        let name = next_macro_name(&self.macros);
+        self.macros.push(Macro::new(&name, &self.input));
+        let saved = match &self.macros_file {
+            Some(path) => save_macros_to(path, &self.macros),
+            None       => Err("No home directory".to_string()),
+        };
+        // Still usable this session when the file can't be written
+        self.top_line = match saved {
+            Ok(())  => format!("SAVED {}:{}", self.macros.len(), name),
+            Err(_)  => format!("{}:{} NOT SAVED", self.macros.len(), name),
+        };
+    }
+
+    /// While a macro runs, `=` takes the typed value for the current `?`;
+    /// after the last one the filled-in formula is evaluated like any input
+    fn macro_key(&mut self, label: &str) -> bool {
+        let Some((index, mut values)) = self.macro_run.take() else { return false };
+        match label {
+            "=" | "EXE" => {
+                values.push(self.input.clone());
+                let m = &self.macros[index];
+                if values.len() < m.placeholders() {
+                    self.macro_run = Some((index, values));
+                    self.input = "0".to_string();
+                    self.prompt_macro();
+                    return true;
+                }
+                match m.expand(&values) {
+                    Ok(expr) => {
+                        self.input = expr;
+                        self.scroll = 0;
+                    }
+                    Err(e) => {
+                        self.input = e;
+                        self.error = true;
+                        return true;
+                    }
+                }
+                false
+            }
+            "AC" => false,
+            _ => {
+                self.macro_run = Some((index, values));
+                false
+            }
+        }
+    }
+
+    fn prompt_macro(&mut self) {
+        if let Some((index, values)) = &self.macro_run {
+            let m = &self.macros[*index];
+            // A macro without placeholders is complete as soon as it's picked
+            if m.placeholders() == 0 {
+                self.input = m.template.clone();
+                self.macro_run = None;
+                self.top_line = m.name.clone();
+                return;
+            }
+            self.top_line = format!("{} ?{}=", m.name, values.len() + 1);
+        }
+    }
+
+    fn start_calc(&mut self) {
+        let Some(formula) = self.calc_formula.clone() else { return };
+        match self.engine.formula_variables(&formula) {
+            Ok(vars) if vars.is_empty() => {
+                self.input = formula;
+                self.evaluate_input();
+            }
+            Ok(vars) => {
+                self.calc_prompt = Some(CalcPrompt { vars, next: 0, fresh: true });
+                self.prompt_calc();
+            }
+            Err(e) => {
+                self.top_line = formula;
+                self.input = e;
+                self.error = true;
+                self.calc_formula = None;
+            }
+        }
+    }
+
+    /// `X?` with X's current value, which `=` keeps unless something is typed over it
+    fn prompt_calc(&mut self) {
+        let Some(prompt) = self.calc_prompt.as_mut() else { return };
+        let var = prompt.vars[prompt.next];
+        prompt.fresh = true;
+        self.top_line = format!("{}?", var);
+        self.input = self.engine.format_result(self.engine.recall(var));
+        self.scroll = 0;
+        self.result = None;
+    }
+
+    /// While CALC prompts, `=` stores the value into the variable asked for;
+    /// after the last one the formula is evaluated with them. CALC starts
+    /// the same formula over; a bad entry shows its error and asks again.
+    fn calc_key(&mut self, label: &str) -> bool {
+        let Some(mut prompt) = self.calc_prompt.take() else { return false };
+        match label {
+            "CALC" => {
+                self.start_calc();
+                true
+            }
+            "=" | "EXE" => {
+                let var = prompt.vars[prompt.next];
+                match self.engine.try_evaluate(&self.input) {
+                    Ok(val) => self.engine.store(var, val),
+                    Err(e) => {
+                        self.input = e;
+                        self.error = true;
+                        prompt.fresh = true;
+                        self.calc_prompt = Some(prompt);
+                        return true;
+                    }
+                }
+                prompt.next += 1;
+                if prompt.next < prompt.vars.len() {
+                    self.calc_prompt = Some(prompt);
+                    self.prompt_calc();
+                } else if let Some(formula) = self.calc_formula.clone() {
+                    self.input = formula;
+                    self.evaluate_input();
+                }
+                true
+            }
+            "AC" => false,
+            _ => {
+                // The first entry key replaces the shown value instead of extending it
+                if prompt.fresh && (label == "(-)" || label.chars().all(|c| c.is_ascii_digit() || c == '.')) {
+                    self.input = "0".to_string();
+                }
+                prompt.fresh = false;
+                self.calc_prompt = Some(prompt);
+                false
+            }
+        }
+    }
+
     fn append(&mut self, s: &str) {
-        if self.input == "0" && s.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false) {
-            self.input = s.to_string();
-        } else if self.error {
+        self.result = None;
+        // Hardware-style entry limit: a key that doesn't fit is ignored
+        if self.input.chars().count() + s.chars().count() > MAX_INPUT_CHARS { return; }
+
+        // Type onto the text left of the cursor, then put the rest back
+        let mut tail = self.input.split_off(self.cursor_byte());
+        if self.overwrite {
+            let covered = tail.char_indices().nth(s.chars().count()).map_or(tail.len(), |(i, _)| i);
+            tail.drain(..covered);
+        }
+        self.append_at_end(s);
+        self.input.push_str(&tail);
+        self.scroll = tail.chars().count();
+    }
+
+    /// Byte offset of the cursor in the input
+    fn cursor_byte(&self) -> usize {
+        let len = self.input.chars().count();
+        let at = len - self.scroll.min(len);
+        self.input.char_indices().nth(at).map_or(self.input.len(), |(i, _)| i)
+    }
+
+    fn append_at_end(&mut self, s: &str) {
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_digit() || c == '.' {
+                self.append_number_char(c);
+                return;
+            }
+        }
+
+        // `!` and `%` only follow an operand: 5!, (1+2)!, A%; the fresh 0 isn't one
+        if (s == "!" || s == "%") && (self.input == "0" || !postfix_allowed(&self.input)) { return; }
+
+        // The placeholder 0 is replaced by anything that starts an operand
+        if self.input == "0" && s.chars().next().map(|c| c.is_alphabetic() || c.is_ascii_digit() || matches!(c, '(' | '√' | '?')).unwrap_or(false) {
             self.input = s.to_string();
-            self.error = false;
         } else {
             self.input.push_str(s);
         }
     }
+
+    /// Keys while the x√y template is open. Entry keys fill the active field;
+    /// anything else closes the template into the input and is handled as usual.
+    fn template_key(&mut self, label: &str) -> bool {
+        let Some(t) = self.template.as_mut() else { return false };
+        match label {
+            "DEL" => {
+                if t.active().pop().is_none() {
+                    if t.field == 1 { t.field = 0; } else { self.template = None; }
+                }
+                true
+            }
+            "AC" => {
+                self.template = None;
+                false
+            }
+            _ if TEMPLATE_KEYS.contains(&label) || label.chars().all(|c| c.is_ascii_digit() || c == '.') => {
+                t.active().push_str(label);
+                true
+            }
+            _ => {
+                self.commit_template();
+                false
+            }
+        }
+    }
+
+    /// ◀ goes back to the template's index, or scrolls the input
+    fn arrow_left(&mut self) {
+        if let Some(t) = self.template.as_mut() {
+            t.field = 0;
+        } else {
+            self.scroll = (self.scroll + 1).min(self.input.chars().count());
+        }
+    }
+
+    /// ▶ steps index → radicand → out of the template, or scrolls back
+    fn arrow_right(&mut self) {
+        match self.template.as_mut() {
+            Some(t) if t.field == 0 => t.field = 1,
+            Some(_) => self.commit_template(),
+            None    => self.scroll = self.scroll.saturating_sub(1),
+        }
+    }
+
+    /// Write the template out as the flat `x√(y)` the engine reads
+    fn commit_template(&mut self) {
+        if let Some(t) = self.template.take() {
+            if self.input == "0" { self.input.clear(); }
+            self.append(&t.to_flat());
+        }
+    }
+
+    /// The one way out of an error: DEL just shows `0`, an operator carries on
+    /// from Ans (`Ans+`), anything else starts fresh. Returns whether the key
+    /// should still be handled.
+    fn clear_error(&mut self, label: &str) -> bool {
+        self.error = false;
+        self.result = None;
+        self.scroll = 0;
+        if label == "DEL" {
+            self.input = "0".to_string();
+            return false;
+        }
+        let continues = !self.shift_mode && ANS_OPERATORS.contains(&label);
+        self.input = if continues { "Ans" } else { "0" }.to_string();
+        true
+    }
+
+    /// Pasted text goes in verbatim at the cursor (over the chars there in
+    /// overwrite mode), clipped to the entry limit
+    fn paste(&mut self, text: &str) {
+        self.result = None;
+        if self.input == "0" || self.error {
+            self.input.clear();
+            self.scroll = 0;
+            self.error = false;
+        }
+        let text: Vec<char> = text.chars().filter(|c| !c.is_control()).collect();
+        let mut tail = self.input.split_off(self.cursor_byte());
+        let tail_len = tail.chars().count();
+        let covered = if self.overwrite { text.len().min(tail_len) } else { 0 };
+        let room = MAX_INPUT_CHARS.saturating_sub(self.input.chars().count() + tail_len - covered);
+        let text = &text[..text.len().min(room)];
+        if self.overwrite {
+            let end = tail.char_indices().nth(text.len()).map_or(tail.len(), |(i, _)| i);
+            tail.drain(..end);
+        }
+        self.input.extend(text);
+        self.input.push_str(&tail);
+        self.scroll = tail.chars().count();
+        if self.input.is_empty() { self.input = "0".to_string(); }
+    }
+
+    /// F↔D: flip the shown value between decimal and its nearest simple fraction
+    fn toggle_fraction_decimal(&mut self) {
+        let val = match self.result {
+            Some(v) => v,
+            None => match self.engine.try_evaluate(&self.input) {
+                Ok(v) => v,
+                Err(_) => return,
+            },
+        };
+        self.scroll = 0;
+        if self.input.contains('⌟') {
+            self.input = self.engine.format_result(val);
+        } else if let Some(frac) = self.engine.approximate_fraction(val) {
+            self.input = frac;
+        }
+        self.result = Some(val);
+    }
+
+    /// SHIFT+°'": flip the shown value between decimal and d°m's"
+    fn toggle_dms(&mut self) {
+        let val = match self.result {
+            Some(v) => v,
+            None => match self.engine.try_evaluate(&self.input) {
+                Ok(v) => v,
+                Err(_) => return,
+            },
+        };
+        self.scroll = 0;
+        self.input = if self.input.contains('°') {
+            self.engine.format_result(val)
+        } else {
+            self.engine.format_dms(val)
+        };
+        self.result = Some(val);
+    }
+
+    /// Put a fresh result on the main line in the form the engine state asks for.
+    /// A Pol/Rec pair goes on the top line; the main line keeps the value
+    /// (r or x), so the next key carries on from something the engine can read.
+    fn show_result(&mut self, val: f64) {
+        if let Some((x, y)) = self.engine.coords {
+            self.top_line = self.engine.format_coords(x, y);
+        }
+        let fraction = if self.engine.fractions { self.engine.format_fraction(val) } else { None };
+        self.input = match fraction {
+            _ if self.engine.dms => self.engine.format_dms(val),
+            Some(f) => f,
+            None    => self.engine.format_result(val),
+        };
+        self.sci_auto = self.engine.is_auto_sci(val);
+        self.scroll = 0;
+        self.result = Some(val);
+    }
+
+    /// Digit / decimal point entry for the number being typed:
+    /// collapses leading zeros, turns a bare `.` into `0.` and ignores a second `.`
+    /// (the point key types `,` in the comma locale)
+    fn append_number_char(&mut self, c: char) {
+        let point = if self.engine.comma_decimal { ',' } else { '.' };
+        let start = self.input.trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == point).len();
+        let number = &self.input[start..];
+        match c {
+            '.' if number.contains(point) => {}
+            '.' if number.is_empty()      => { self.input.push('0'); self.input.push(point); }
+            '.'                           => self.input.push(point),
+            _ if number == "0"          => {
+                self.input.truncate(start);
+                self.input.push(c);
+            }
+            _ => self.input.push(c),
+        }
+    }
+}
+
+// ─── CALC ───────────────────────────────────────────────────
+
+/// A CALC run: the variables its formula reads and which one is being asked for
+#[derive(Debug, Clone)]
+struct CalcPrompt {
+    vars:  Vec<char>,
+    next:  usize,
+    /// Nothing typed since the prompt showed the current value
+    fresh: bool,
+}
+
+// ─── Natural entry template (CG50) ─────────────────────────
+
+/// x√y entry on the CG50: index and radicand are filled in place and
+/// ◀/▶ moves between them
+#[derive(Debug, Clone, Default)]
+struct RootTemplate {
+    index:    String,
+    radicand: String,
+    /// 0 = index, 1 = radicand
+    field:    usize,
+}
+
+impl RootTemplate {
+    fn active(&mut self) -> &mut String {
+        if self.field == 0 { &mut self.index } else { &mut self.radicand }
+    }
+
+    /// `3√(8)`; an empty index leaves a plain `√(8)`, i.e. the square root
+    fn to_flat(&self) -> String {
+        format!("{}√({})", self.index, self.radicand)
+    }
+}
+
+/// Draw the template as a small raised index, `√`, then the radicand;
+/// the field being typed into is shaded and an empty one shows `□`
+fn append_template(job: &mut egui::text::LayoutJob, t: &RootTemplate, font_size: f32, color: Color32) {
+    let fields = [(&t.index, font_size * 0.6, egui::Align::TOP), (&t.radicand, font_size, egui::Align::BOTTOM)];
+    for (i, (text, size, valign)) in fields.into_iter().enumerate() {
+        if i == 1 {
+            job.append("√(", 0.0, egui::TextFormat {
+                font_id: FontId::monospace(font_size),
+                color,
+                ..Default::default()
+            });
+        }
+        job.append(if text.is_empty() { "□" } else { text }, 0.0, egui::TextFormat {
+            font_id:    FontId::monospace(size),
+            color,
+            background: if t.field == i { color.linear_multiply(0.2) } else { Color32::TRANSPARENT },
+            valign,
+            ..Default::default()
+        });
+    }
+    job.append(")", 0.0, egui::TextFormat {
+        font_id: FontId::monospace(font_size),
+        color,
+        ..Default::default()
+    });
+}
+
+/// Keys (besides digits) that type into a template field
+const TEMPLATE_KEYS: &[&str] = &["+", "−", "×", "÷", "(", ")", "Ans", "A", "B", "C", "D", "E", "F", "X", "Y", "M"];
+
+/// The macro menu numbers its entries 1-9
+const MAX_MACROS: usize = 9;
+
+/// Keys that, pressed on an error, continue from Ans instead of starting over
+const ANS_OPERATORS: &[&str] = &["+", "−", "-", "×", "÷", "^", "x²", "x⁻¹"];
+
+/// Whether a postfix `!` / `%` may follow `before`: a number, `)`, a
+/// variable or constant, or another postfix
+fn postfix_allowed(before: &str) -> bool {
+    if before.ends_with("Ans") { return true; }
+    before.chars().last().is_some_and(|c| {
+        c.is_ascii_digit() || matches!(c, '.' | ')' | 'π' | 'ℯ' | '!' | '%' | '²' | '³') || VARIABLES.contains(&c)
+    })
+}
+
+/// A fresh engine with the config's settings applied
+fn configured_engine(config: &AppConfig) -> CalcEngine {
+    let mut engine = CalcEngine::new();
+    engine.angle = config.angle;
+    engine.format = config.format;
+    engine.radian_hint = config.radian_hint;
+    engine.comma_decimal = config.comma_decimal;
+    engine.rounded_ans = config.rounded_ans;
+    engine.base_n = config.base_n;
+    engine.set_display_digits(config.model.digits());
+    engine
+}
+
+fn palette_for(model: ModelType, accessible: bool) -> Palette {
+    if accessible { Palette::accessible(model) } else { Palette::for_model(model) }
+}
+
+// ─── Shell geometry ─────────────────────────────────────────
+
+const KEY_H:     f32 = 36.0;
+const KEY_GAP:   f32 = 4.0;
+const MIN_KEY_W: f32 = 48.0;
+/// Header + display + model switcher + padding above the keys
+const CHROME_H:  f32 = 360.0;
+/// Shell border + inner margins around the content column
+const SHELL_PAD: f32 = 30.0;
+const MIN_SHELL: Vec2 = Vec2::new(340.0, 600.0);
+
+/// Calculator body size for a model, from its key grid's rows and widest row
+fn shell_size(model: ModelType) -> Vec2 {
+    let grid = button_grid(model);
+    let cols = grid.iter().map(|row| row.len()).max().unwrap_or(1) as f32;
+    let keys_w = cols * MIN_KEY_W + (cols - 1.0) * KEY_GAP;
+    let keys_h = grid.len() as f32 * (KEY_H + KEY_GAP);
+    Vec2::new(
+        (keys_w + SHELL_PAD).max(MIN_SHELL.x),
+        (CHROME_H + keys_h).max(MIN_SHELL.y),
+    )
+}
+
+/// Width available to the display and key rows inside the shell
+fn content_width(model: ModelType) -> f32 {
+    shell_size(model).x - SHELL_PAD
+}
+
+/// Window inner size that fits the shell plus its drop shadow and margins
+pub fn window_size(model: ModelType) -> Vec2 {
+    shell_size(model) + Vec2::new(60.0, 30.0)
+}
+
+// ─── Display window ─────────────────────────────────────────
+
+/// Longest expression the input buffer accepts
+const MAX_INPUT_CHARS: usize = 256;
+
+/// Characters that fit on the main display line at the small font
+const DISPLAY_CHARS: usize = 24;
+
+/// Caret on/off half-period
+const CARET_BLINK_SECS: f64 = 0.5;
+
+/// Quiet time after an edit before the live preview evaluates the input
+const PREVIEW_DEBOUNCE_SECS: f64 = 0.25;
+
+/// Whether the blinking caret is lit `elapsed_secs` into the animation clock
+fn caret_visible(elapsed_secs: f64) -> bool {
+    (elapsed_secs / CARET_BLINK_SECS).floor() as i64 % 2 == 0
+}
+
+/// The slice of `input` shown on the display, ending at char index `cursor`
+/// (or at the window width, whichever is further right). A `◄` / `►` replaces
+/// the first / last visible char when more text is hidden on that side.
+fn visible_window(input: &str, cursor: usize, width: usize) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    if chars.len() <= width { return input.to_string(); }
+
+    let end = cursor.max(width).min(chars.len());
+    let start = end - width;
+    let mut shown: Vec<char> = chars[start..end].to_vec();
+    if start > 0 { shown[0] = '◄'; }
+    if end < chars.len() { shown[width - 1] = '►'; }
+    shown.into_iter().collect()
+}
+
+// ─── Syntax highlighting ────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    Plain,
+    Number,
+    Func,
+    Operator,
+    /// Nesting depth of the bracket pair, 0 = outermost
+    Paren(usize),
+    /// The bracket just left of the cursor and its partner
+    Matched,
+}
+
+/// Split display text into runs to colour. Doesn't evaluate anything, so
+/// half-typed input still colours; `None` (draw plain) on a char it can't place.
+/// A bracket just left of `cursor` is paired up with its partner as `Matched`.
+fn highlight_segments(text: &str, cursor: usize) -> Option<Vec<(String, Segment)>> {
+    let chars: Vec<char> = text.chars().collect();
+    let matched = cursor.checked_sub(1).and_then(|at| {
+        matching_bracket(&chars, at).map(|partner| [at, partner])
+    });
+    let mut out: Vec<(String, Segment)> = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let word = crate::engine::FUNCS.iter().copied()
+            .chain(["Ans", "and", "xor", "or", "not"])
+            .find(|w| starts_with_at(&chars, i, w));
+        let (len, kind) = match (word, c) {
+            (Some(w), _) => (w.chars().count(), Segment::Func),
+            (_, '0'..='9' | '.' | '⌟' | '°' | '\'' | '"' | 'π' | 'ℯ' | 'e') => (1, Segment::Number),
+            (_, '(') => { depth += 1; (1, Segment::Paren(depth - 1)) }
+            (_, ')') => { depth = depth.saturating_sub(1); (1, Segment::Paren(depth)) }
+            (_, '+' | '-' | '−' | '×' | '÷' | '*' | '/' | '^' | '√' | '²' | '³' | '!' | '%'
+                | ',' | ';' | '→' | '=' | '<' | '>') => (1, Segment::Operator),
+            (_, c) if c.is_ascii_alphabetic() || c == ' ' || c == '◄' || c == '►' => (1, Segment::Plain),
+            _ => return None,
+        };
+        let kind = if matched.is_some_and(|m| m.contains(&i)) { Segment::Matched } else { kind };
+        let piece: String = chars[i..i + len].iter().collect();
+        match out.last_mut() {
+            Some((run, k)) if *k == kind && !matches!(kind, Segment::Paren(_) | Segment::Matched) => {
+                run.push_str(&piece)
+            }
+            _ => out.push((piece, kind)),
+        }
+        i += len;
+    }
+    Some(out)
+}
+
+/// Index of the bracket pairing with the one at `at`, if `at` is a bracket
+fn matching_bracket(chars: &[char], at: usize) -> Option<usize> {
+    let mut depth = 0i32;
+    match chars.get(at)? {
+        '(' => {
+            for (i, &c) in chars.iter().enumerate().skip(at) {
+                if c == '(' { depth += 1; }
+                if c == ')' { depth -= 1; }
+                if depth == 0 { return Some(i); }
+            }
+        }
+        ')' => {
+            for i in (0..=at).rev() {
+                if chars[i] == ')' { depth += 1; }
+                if chars[i] == '(' { depth -= 1; }
+                if depth == 0 { return Some(i); }
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+/// Bracket pairs cycle through these by depth
+const PAREN_COLORS: [Color32; 3] = [
+    Color32::from_rgb(120, 40, 140),
+    Color32::from_rgb(20, 120, 110),
+    Color32::from_rgb(170, 110, 0),
+];
+
+fn segment_color(kind: Segment, text: Color32) -> Color32 {
+    match kind {
+        Segment::Plain | Segment::Number => text,
+        Segment::Func     => Color32::from_rgb(30, 80, 170),
+        Segment::Operator => Color32::from_rgb(170, 60, 20),
+        Segment::Paren(d) => PAREN_COLORS[d % PAREN_COLORS.len()],
+        Segment::Matched  => text,
+    }
+}
+
+/// sin/cos/tan with hyp and/or SHIFT applied: hyp+SHIFT+sin → asinh
+fn trig_name(base: &str, hyp: bool, inverse: bool) -> String {
+    format!("{}{}{}", if inverse { "a" } else { "" }, base, if hyp { "h" } else { "" })
+}
+
+// ─── Keyboard shortcuts ─────────────────────────────────────
+
+/// Alt+key → calculator button label. Plain letters are never bound,
+/// so typed text can't collide with these.
+const FUNCTION_KEYS: &[(egui::Key, &str)] = &[
+    (egui::Key::S, "sin"),
+    (egui::Key::C, "cos"),
+    (egui::Key::T, "tan"),
+    (egui::Key::L, "log"),
+    (egui::Key::N, "ln"),
+    (egui::Key::R, "√"),
+    (egui::Key::P, "π"),
+    (egui::Key::X, "LaTeX"),
+    (egui::Key::V, "VARS"),
+    (egui::Key::H, "RGB"),
+];
+
+fn function_for_key(key: egui::Key) -> Option<&'static str> {
+    FUNCTION_KEYS.iter().find(|(k, _)| *k == key).map(|(_, label)| *label)
+}
+
+/// Calculator keys only fire while no text field or popup menu owns the keyboard
+fn should_handle_global_keys(text_focused: bool, popup_open: bool) -> bool {
+    !text_focused && !popup_open
 }
 
 // ─── Color helpers ──────────────────────────────────────────
@@ -606,3 +1935,543 @@ fn lighten(c: Color32, factor: f32) -> Color32 {
         ((c.b() as f32 * factor).min(255.0)) as u8,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> CasioApp {
+        CasioApp::from_config(AppConfig::default())
+    }
+
+    fn cg50() -> CasioApp {
+        CasioApp::from_config(AppConfig { model: ModelType::FxCG50, ..AppConfig::default() })
+    }
+
+    fn press(app: &mut CasioApp, keys: &[&str]) {
+        for key in keys {
+            app.handle_button(key);
+        }
+    }
+
+    #[test]
+    fn config_reaches_the_engine() {
+        let app = CasioApp::from_config(AppConfig {
+            model:  ModelType::FxCG50,
+            angle:  AngleMode::Gradians,
+            format: DisplayFormat::Fix(3),
+            comma_decimal: true,
+            ..AppConfig::default()
+        });
+        assert_eq!(app.model, ModelType::FxCG50);
+        assert_eq!(app.engine.angle, AngleMode::Gradians);
+        assert_eq!(app.engine.format, DisplayFormat::Fix(3));
+        assert!(app.engine.comma_decimal);
+        assert_eq!(app.input, "0");
+    }
+
+    #[test]
+    fn shift_rcl_clears_the_m_indicator() {
+        let mut app = app();
+        press(&mut app, &["5", "M+"]);
+        assert!(app.engine.memory_in_use());
+        press(&mut app, &["SHIFT", "RCL"]);
+        assert!(!app.engine.memory_in_use());
+        assert_eq!(app.top_line, "M CLEARED");
+    }
+
+    #[test]
+    fn alt_keymap_lookup() {
+        assert_eq!(function_for_key(egui::Key::S), Some("sin"));
+        assert_eq!(function_for_key(egui::Key::N), Some("ln"));
+        assert_eq!(function_for_key(egui::Key::P), Some("π"));
+        assert_eq!(function_for_key(egui::Key::Q), None);
+    }
+
+    #[test]
+    fn number_entry_is_normalized() {
+        for (keys, want) in [
+            (&[".", "5"][..], "0.5"),
+            (&["0", "0", "7"][..], "7"),
+            (&["0", ".", "0", "."][..], "0.0"),
+            (&["0", "0", ".", "5"][..], "0.5"),
+        ] {
+            let mut app = app();
+            press(&mut app, keys);
+            assert_eq!(app.input, want, "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn cg50_has_hyperbolics() {
+        assert!(button_grid(ModelType::FxCG50).iter().flatten().any(|b| b.label == "hyp"));
+        let mut app = cg50();
+        press(&mut app, &["hyp", "sin"]);
+        assert_eq!(app.input, "sinh(");
+        let mut app = cg50();
+        press(&mut app, &["hyp", "SHIFT", "sin"]);
+        assert_eq!(app.input, "asinh(");
+    }
+
+    #[test]
+    fn visible_window_follows_the_cursor() {
+        assert_eq!(visible_window("12345", 5, 8), "12345");
+        // Cursor at the end: the head is hidden
+        assert_eq!(visible_window("123456789", 9, 5), "◄6789");
+        // Cursor at the start: the window sits at the left edge
+        assert_eq!(visible_window("123456789", 0, 5), "1234►");
+        // Cursor in the middle: hidden text on both sides
+        assert_eq!(visible_window("123456789", 7, 5), "◄456►");
+    }
+
+    #[test]
+    fn cg50_exp_key_enters_an_exponent() {
+        let mut app = cg50();
+        press(&mut app, &["2", "EXP", "3", "="]);
+        assert_eq!(app.result, Some(2000.0));
+        let mut app = cg50();
+        press(&mut app, &["1", ".", "5", "EXP", "(-)", "2", "="]);
+        assert_eq!(app.result, Some(0.015));
+    }
+
+    #[test]
+    fn append_stops_at_the_entry_limit() {
+        let mut app = app();
+        for _ in 0..MAX_INPUT_CHARS + 10 {
+            app.append("1");
+        }
+        assert_eq!(app.input.chars().count(), MAX_INPUT_CHARS);
+        app.append("sin(");
+        assert_eq!(app.input.chars().count(), MAX_INPUT_CHARS);
+    }
+
+    #[test]
+    fn shell_size_follows_the_grid() {
+        for model in [ModelType::Fx82MS, ModelType::Fx991ES, ModelType::FxCG50] {
+            let grid = button_grid(model);
+            let cols = grid.iter().map(|row| row.len()).max().unwrap() as f32;
+            let size = shell_size(model);
+            assert!(size.x >= cols * MIN_KEY_W + (cols - 1.0) * KEY_GAP + SHELL_PAD);
+            assert!(size.y >= CHROME_H + grid.len() as f32 * (KEY_H + KEY_GAP));
+            assert!(size.x >= MIN_SHELL.x && size.y >= MIN_SHELL.y);
+            assert_eq!(window_size(model), size + Vec2::new(60.0, 30.0));
+        }
+    }
+
+    #[test]
+    fn x_root_takes_the_index_before_it() {
+        for (keys, want) in [
+            (&["3", "SHIFT", "√"][..], "3√("),
+            // No index: √( reads as the square root, never `0√(`
+            (&["SHIFT", "√"][..], "√("),
+            (&["2", "+", "SHIFT", "√"][..], "2+√("),
+        ] {
+            let mut app = app();
+            press(&mut app, keys);
+            assert_eq!(app.input, want, "{:?}", keys);
+        }
+        for (keys, want) in [(&["3", "SHIFT", "√", "8", "="][..], 2.0), (&["SHIFT", "√", "9", "="], 3.0)] {
+            let mut app = app();
+            press(&mut app, keys);
+            assert_eq!(app.result, Some(want), "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn each_grid_has_one_x_root_key() {
+        for model in [ModelType::Fx82MS, ModelType::Fx991ES, ModelType::FxCG50] {
+            let keys = button_grid(model).into_iter().flatten().filter(|b| b.shift_label == Some("x√")).count();
+            assert_eq!(keys, 1, "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn shifted_square_key_is_square_root() {
+        for model in [ModelType::Fx82MS, ModelType::Fx991ES, ModelType::FxCG50] {
+            let key = button_grid(model).into_iter().flatten().find(|b| b.label == "x²").expect("x² key");
+            assert_eq!(key.shift_label, Some("√"), "{:?}", model);
+            let mut app = CasioApp::from_config(AppConfig { model, ..AppConfig::default() });
+            press(&mut app, &["9", "SHIFT", "x²", "="]);
+            assert_eq!(app.result, Some(3.0), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn shifted_log_keys_are_antilogs() {
+        for (key, want) in [("log", "10^("), ("ln", "exp(")] {
+            let mut app = app();
+            press(&mut app, &["SHIFT", key]);
+            assert_eq!(app.input, want);
+        }
+    }
+
+    #[test]
+    fn negative_sign_key_is_unary_minus() {
+        for (keys, want) in [
+            (&["(-)", "5", "="][..], -5.0),
+            (&["2", "^", "(-)", "3", "="][..], 0.125),
+            (&["3", "×", "(-)", "4", "="][..], -12.0),
+        ] {
+            let mut app = app();
+            press(&mut app, keys);
+            assert_eq!(app.result, Some(want), "{:?}", keys);
+        }
+    }
+
+    #[test]
+    fn on_keeps_the_model_and_config() {
+        let mut app = CasioApp::from_config(AppConfig {
+            model:         ModelType::FxCG50,
+            angle:         AngleMode::Radians,
+            comma_decimal: true,
+            ..AppConfig::default()
+        });
+        press(&mut app, &["ON", "1", "÷", "3", "="]);
+        assert_eq!(app.engine.angle, AngleMode::Radians);
+        assert!(app.engine.comma_decimal);
+        assert_eq!(app.engine.format_result(1.0 / 3.0), "0,333333333333333");
+    }
+
+    #[test]
+    fn base_n_comes_from_the_config() {
+        assert!(!app().engine.base_n);
+        let mut app = CasioApp::from_config(AppConfig { base_n: true, ..AppConfig::default() });
+        press(&mut app, &["ON"]);
+        assert_eq!(app.engine.try_evaluate("6 and 3"), Ok(2.0));
+    }
+
+    #[test]
+    fn dms_subtraction_borrows_through_the_minus_key() {
+        let mut app = app();
+        let d = "°'\"";
+        press(&mut app, &["1", d, "0", d, "0", d, "−", "0", d, "0", d, "1", d]);
+        assert_eq!(app.input, "1°0'0\"−0°0'1\"");
+        press(&mut app, &["="]);
+        assert_eq!(app.input, "0°59'59\"");
+    }
+
+    #[test]
+    fn shift_hyp_is_log2_on_the_991es_only() {
+        let mut es = CasioApp::from_config(AppConfig { model: ModelType::Fx991ES, ..AppConfig::default() });
+        press(&mut es, &["SHIFT", "hyp", "8", ")", "="]);
+        assert_eq!(es.result, Some(3.0));
+        let mut cg = cg50();
+        press(&mut cg, &["SHIFT", "hyp", "sin"]);
+        assert!(!cg.input.contains("log₂"));
+        assert_eq!(cg.input, "asinh(");
+    }
+
+    #[test]
+    fn global_keys_wait_for_text_fields_and_popups() {
+        assert!(should_handle_global_keys(false, false));
+        assert!(!should_handle_global_keys(true, false));
+        assert!(!should_handle_global_keys(false, true));
+        assert!(!should_handle_global_keys(true, true));
+    }
+
+    #[test]
+    fn keys_after_an_error() {
+        let error = |keys: &[&str]| {
+            let mut app = app();
+            press(&mut app, &["1", "÷", "0", "="]);
+            assert!(app.error);
+            press(&mut app, keys);
+            assert!(!app.error);
+            app.input.clone()
+        };
+        assert_eq!(error(&["DEL"]), "0");
+        assert_eq!(error(&["+"]), "Ans+");
+        assert_eq!(error(&["7"]), "7");
+    }
+
+    #[test]
+    fn preview_waits_for_typing_to_pause() {
+        let mut app = app();
+        press(&mut app, &["2", "+", "3"]);
+        assert_eq!(app.live_preview(10.0), None);
+        assert_eq!(app.preview_wait(10.0), Some(PREVIEW_DEBOUNCE_SECS));
+        assert_eq!(app.live_preview(10.1), None);
+        // Another edit starts the wait over
+        press(&mut app, &["×", "4"]);
+        assert_eq!(app.live_preview(10.2), None);
+        assert_eq!(app.live_preview(10.3), None);
+        assert_eq!(app.live_preview(10.5), Some("14".to_string()));
+        assert_eq!(app.preview_wait(10.5), None);
+    }
+
+    #[test]
+    fn pol_result_keeps_calculating() {
+        let mut app = app();
+        press(&mut app, &["Pol(", "3", ",", "4", ")", "="]);
+        assert_eq!(app.top_line, "(3,4)");
+        assert_eq!(app.input, "5");
+        press(&mut app, &["+", "1", "="]);
+        assert!(!app.error, "{}", app.input);
+        assert_eq!(app.result, Some(6.0));
+
+        // The r∠θ form is only shown; the main line stays r
+        press(&mut app, &["AC", "Pol(", "3", ",", "4", ")", "=", "SHIFT", "ENG"]);
+        assert_eq!(app.top_line, "5∠53.13010235°");
+        press(&mut app, &["+", "1", "="]);
+        assert_eq!(app.result, Some(6.0));
+    }
+
+    #[test]
+    fn highlight_splits_into_runs() {
+        let seg = |s: &str, k: Segment| (s.to_string(), k);
+        assert_eq!(highlight_segments("sin(12+3)", 0), Some(vec![
+            seg("sin", Segment::Func), seg("(", Segment::Paren(0)), seg("12", Segment::Number),
+            seg("+", Segment::Operator), seg("3", Segment::Number), seg(")", Segment::Paren(0)),
+        ]));
+        // The bracket before the cursor lights up with its partner
+        assert_eq!(highlight_segments("((1))", 4), Some(vec![
+            seg("(", Segment::Paren(0)), seg("(", Segment::Matched), seg("1", Segment::Number),
+            seg(")", Segment::Matched), seg(")", Segment::Paren(0)),
+        ]));
+        assert_eq!(highlight_segments("2@3", 0), None);
+    }
+
+    #[test]
+    fn root_template_fills_index_then_radicand() {
+        let mut app = cg50();
+        press(&mut app, &["SHIFT", "^", "3"]);
+        app.arrow_right();
+        press(&mut app, &["8"]);
+        assert_eq!(app.template.as_ref().map(RootTemplate::to_flat).as_deref(), Some("3√(8)"));
+        app.arrow_left();
+        press(&mut app, &["DEL", "2"]);
+        app.arrow_right();
+        app.arrow_right();
+        assert!(app.template.is_none());
+        assert_eq!(app.input, "2√(8)");
+        press(&mut app, &["="]);
+        assert!((app.result.unwrap() - 8f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn repeated_ans_gets_a_times() {
+        let mut app = app();
+        press(&mut app, &["3", "=", "AC", "Ans", "Ans"]);
+        assert_eq!(app.input, "Ans×Ans");
+        press(&mut app, &["="]);
+        assert_eq!(app.result, Some(9.0));
+        // Only the text left of the cursor counts
+        press(&mut app, &["AC", "Ans", "+", "1"]);
+        app.arrow_left();
+        app.arrow_left();
+        press(&mut app, &["Ans"]);
+        assert_eq!(app.input, "Ans×Ans+1");
+        press(&mut app, &["AC", "2", "+", "Ans"]);
+        for _ in 0..3 { app.arrow_left(); }
+        press(&mut app, &["Ans"]);
+        // …and an Ans after the cursor doesn't make it `2+×AnsAns`
+        assert_eq!(app.input, "2+AnsAns");
+    }
+
+    #[test]
+    fn macro_menu_saves_the_input() {
+        let path = std::env::temp_dir().join(format!("casio-calc-ui-macros-{}", std::process::id()));
+        let mut app = app();
+        app.macros = crate::macros::default_macros();
+        app.macros_file = Some(path.clone());
+        press(&mut app, &["?", "×", "2", "ALPHA", "MODE"]);
+        assert!(app.top_line.ends_with("=:SAVE"));
+        press(&mut app, &["="]);
+        assert_eq!(app.top_line, "SAVED 4:m1");
+        let saved = crate::macros::parse_macros(&std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).ok();
+        assert_eq!(saved.last(), Some(&Macro::new("m1", "?×2")));
+        // And it runs like the others
+        press(&mut app, &["ALPHA", "MODE", "4", "2", "1", "="]);
+        assert_eq!(app.result, Some(42.0));
+    }
+
+    #[test]
+    fn caret_blinks_each_half_second() {
+        assert!(caret_visible(0.0));
+        assert!(caret_visible(0.49));
+        assert!(!caret_visible(0.5));
+        assert!(!caret_visible(0.99));
+        assert!(caret_visible(1.0));
+    }
+
+    #[test]
+    fn paste_goes_in_at_the_cursor() {
+        let mut app = app();
+        press(&mut app, &["1", "+", "2", "="]);
+        assert!(app.result.is_some());
+        app.paste("×3");
+        assert_eq!(app.result, None);
+        app.input = "1+2".to_string();
+        app.scroll = 1;
+        app.paste("×3");
+        assert_eq!(app.input, "1+×32");
+        assert_eq!(app.scroll, 1);
+        app.overwrite = true;
+        app.scroll = 3;
+        app.paste("7");
+        assert_eq!(app.input, "1+732");
+    }
+
+    #[test]
+    fn paste_stops_at_the_entry_limit() {
+        let mut app = app();
+        app.paste(&"9".repeat(MAX_INPUT_CHARS + 10));
+        assert_eq!(app.input.chars().count(), MAX_INPUT_CHARS);
+        app.scroll = 5;
+        app.overwrite = true;
+        app.paste("123");
+        assert_eq!(app.input.chars().count(), MAX_INPUT_CHARS);
+        assert!(app.input.ends_with("12399"));
+    }
+
+    #[test]
+    fn cg50_calc_reruns_a_formula() {
+        assert!(button_grid(ModelType::FxCG50).iter().flatten().any(|b| b.label == "CALC"));
+        let mut app = cg50();
+        press(&mut app, &["A", "×", "2", "+", "1", "CALC"]);
+        assert_eq!(app.top_line, "A?");
+        press(&mut app, &["3", "="]);
+        assert_eq!(app.result, Some(7.0));
+        press(&mut app, &["CALC", "1", "0", "="]);
+        assert_eq!(app.result, Some(21.0));
+    }
+
+    #[test]
+    fn calc_mid_prompt_starts_the_formula_over() {
+        let mut app = cg50();
+        press(&mut app, &["A", "×", "2", "+", "B", "CALC", "3", "CALC"]);
+        assert_eq!(app.top_line, "A?");
+        assert_eq!(app.calc_formula.as_deref(), Some("A×2+B"));
+        press(&mut app, &["3", "=", "4", "="]);
+        assert_eq!(app.result, Some(10.0));
+    }
+
+    #[test]
+    fn bad_calc_entry_asks_again() {
+        let mut app = cg50();
+        press(&mut app, &["A", "×", "2", "+", "1", "CALC", "1", "÷", "0", "="]);
+        assert!(app.error);
+        assert_eq!(app.top_line, "A?");
+        press(&mut app, &["5", "="]);
+        assert!(!app.error);
+        assert_eq!(app.result, Some(11.0));
+    }
+
+    #[test]
+    fn shift_dms_toggles_the_shown_value() {
+        for (keys, dms, decimal) in [(["1", ".", "5"], "1°30'0\"", "1.5"), (["0", ".", "25"], "0°15'0\"", "0.25")] {
+            let mut app = app();
+            press(&mut app, &keys);
+            press(&mut app, &["=", "SHIFT", "°'\""]);
+            assert_eq!(app.input, dms);
+            press(&mut app, &["SHIFT", "°'\""]);
+            assert_eq!(app.input, decimal);
+        }
+    }
+
+    #[test]
+    fn del_on_empty_restores_when_enabled() {
+        for restore in [false, true] {
+            let mut app = CasioApp::from_config(AppConfig { restore_on_del: restore, ..AppConfig::default() });
+            press(&mut app, &["1", "2", "×", "3", "=", "AC", "DEL"]);
+            assert_eq!(app.input, if restore { "12×3" } else { "0" });
+        }
+    }
+
+    #[test]
+    fn postfix_needs_an_operand() {
+        for before in ["5", "(1+2)", "A", "Ans", "π", "5!"] {
+            assert!(postfix_allowed(before), "{}", before);
+        }
+        for before in ["", "2+", "sin(", "×"] {
+            assert!(!postfix_allowed(before), "{}", before);
+        }
+        let mut app = app();
+        press(&mut app, &["5", "!", "="]);
+        assert_eq!(app.result, Some(120.0));
+        press(&mut app, &["AC", "!", "5"]);
+        assert_eq!(app.input, "5");
+        press(&mut app, &["0", "%", "="]);
+        assert_eq!(app.result, Some(0.5));
+    }
+
+    #[test]
+    fn shift_equals_flips_to_full_precision() {
+        let mut app = app();
+        press(&mut app, &["1", "÷", "3", "=", "SHIFT", "="]);
+        assert_eq!(app.input, "0.3333333333333333");
+        press(&mut app, &["SHIFT", "="]);
+        assert_eq!(app.input, "0.3333333333");
+    }
+
+    #[test]
+    fn equals_on_nothing_leaves_the_display() {
+        let mut app = app();
+        press(&mut app, &["5", "+", "="]);
+        assert_eq!(app.result, Some(5.0));
+        press(&mut app, &["AC", "="]);
+        assert_eq!(app.result, Some(0.0));
+        assert!(!app.error);
+        app.input = "×".to_string();
+        press(&mut app, &["="]);
+        assert!(!app.error);
+        assert_eq!(app.input, "×");
+    }
+
+    #[test]
+    fn clr_setup_restores_the_model_default_angle() {
+        let mut app = cg50();
+        app.engine.angle = AngleMode::Gradians;
+        app.engine.format = DisplayFormat::Fix(2);
+        press(&mut app, &["SHIFT", "MODE", "1"]);
+        assert_eq!(app.engine.angle, AngleMode::Radians);
+        assert_eq!(app.engine.format, DisplayFormat::Normal);
+        app.engine.angle = AngleMode::Gradians;
+        press(&mut app, &["SHIFT", "MODE", "2"]);
+        assert_eq!(app.engine.angle, AngleMode::Gradians);
+    }
+
+    #[test]
+    fn each_model_keeps_its_own_setup() {
+        let mut app = app();
+        app.engine.format = DisplayFormat::Fix(3);
+        app.switch_model(ModelType::FxCG50);
+        assert_eq!((app.engine.angle, app.engine.format), (AngleMode::Radians, DisplayFormat::Normal));
+        app.engine.angle = AngleMode::Gradians;
+        app.switch_model(ModelType::Fx82MS);
+        assert_eq!((app.engine.angle, app.engine.format), (AngleMode::Degrees, DisplayFormat::Fix(3)));
+        app.switch_model(ModelType::FxCG50);
+        assert_eq!(app.engine.angle, AngleMode::Gradians);
+    }
+
+    #[test]
+    fn formula_menu_fills_in_by_calc() {
+        let key = button_grid(ModelType::FxCG50).into_iter().flatten().find(|b| b.label == "CALC").unwrap();
+        assert_eq!(key.alpha_label, Some("FORMULA"));
+        let mut app = cg50();
+        press(&mut app, &["ALPHA", "CALC"]);
+        assert!(app.top_line.starts_with("1:quad"));
+        press(&mut app, &["1"]);
+        assert_eq!(app.calc_formula.as_deref(), Some(FORMULAS[0].expr));
+        let mut asked = Vec::new();
+        // Asked in the order the formula reads them
+        for value in [&["(-)", "3"][..], &["1"], &["2"]] {
+            asked.push(app.top_line.clone());
+            press(&mut app, value);
+            press(&mut app, &["="]);
+        }
+        assert_eq!(asked, ["B?", "A?", "C?"]);
+        assert_eq!(app.result, Some(2.0));
+        press(&mut app, &["ALPHA", "CALC", "3", "2", "="]);
+        assert!((app.result.unwrap() - 4.0 * std::f64::consts::PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn history_keeps_the_glyphs_as_typed() {
+        let mut app = app();
+        press(&mut app, &["9", "−", "3", "×", "4", "÷", "2", "="]);
+        assert_eq!(app.result, Some(3.0));
+        assert_eq!(app.engine.history.back().map(|(e, _)| e.as_str()), Some("9−3×4÷2"));
+    }
+}